@@ -0,0 +1,2 @@
+ALTER TABLE polls
+ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
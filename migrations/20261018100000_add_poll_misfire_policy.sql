@@ -0,0 +1,7 @@
+ALTER TABLE polls
+ADD COLUMN misfire_policy TEXT NOT NULL DEFAULT 'fire_once';
+
+-- last time the sender looked for occurrences of this poll, missed occurrences
+-- are searched from there
+ALTER TABLE polls
+ADD COLUMN last_evaluated_at TIMESTAMPTZ;
@@ -0,0 +1,6 @@
+ALTER TABLE polls
+ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- the poll is not sent before this date, even when enabled
+ALTER TABLE polls
+ADD COLUMN paused_until TIMESTAMPTZ;
@@ -0,0 +1,12 @@
+ALTER TABLE polls
+ADD COLUMN start_at TIMESTAMPTZ;
+
+ALTER TABLE polls
+ADD COLUMN end_at TIMESTAMPTZ;
+
+-- the poll is retired once it has been sent this many times
+ALTER TABLE polls
+ADD COLUMN max_occurrences INT;
+
+ALTER TABLE polls
+ADD COLUMN occurrences_sent INT NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- discord snowflakes of the poll target, the guild and channel names are
+-- only used to resolve them once
+ALTER TABLE polls
+ADD COLUMN guild_id BIGINT;
+
+ALTER TABLE polls
+ADD COLUMN channel_id BIGINT;
@@ -0,0 +1,14 @@
+-- guilds and channels the bot can post polls into, refreshed by the sender
+CREATE TABLE guilds(
+	id BIGINT PRIMARY KEY,
+	name TEXT NOT NULL
+);
+
+CREATE TABLE guild_channels(
+	id BIGINT PRIMARY KEY,
+	guild_id BIGINT NOT NULL,
+	name TEXT NOT NULL,
+	kind TEXT NOT NULL,
+	parent_id BIGINT,
+	FOREIGN KEY (guild_id) REFERENCES guilds(id) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+-- one row per user and answer, the answer votes are counted from them
+CREATE TABLE poll_votes(
+	instance_id BIGINT NOT NULL,
+	answer_id BIGINT NOT NULL,
+	user_id BIGINT NOT NULL,
+	PRIMARY KEY (instance_id, answer_id, user_id),
+	FOREIGN KEY (instance_id) REFERENCES poll_instances(id) ON DELETE CASCADE
+);
+
+-- whether the voters can be listed through the api
+ALTER TABLE polls
+ADD COLUMN expose_voters BOOLEAN NOT NULL DEFAULT FALSE;
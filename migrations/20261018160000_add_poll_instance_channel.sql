@@ -0,0 +1,3 @@
+-- channel the poll message was posted in, a thread when posted in a forum
+ALTER TABLE poll_instances
+ADD COLUMN channel_id BIGINT;
@@ -0,0 +1,20 @@
+ALTER TABLE poll_instances
+ADD COLUMN ends_at TIMESTAMPTZ;
+
+ALTER TABLE poll_instances
+ADD COLUMN status TEXT NOT NULL DEFAULT 'open';
+
+-- set once the final results have been fetched from discord
+ALTER TABLE poll_instances
+ADD COLUMN finalized_at TIMESTAMPTZ;
+
+UPDATE poll_instances pi
+SET ends_at = to_timestamp(pi.sent_at + p.duration)
+FROM polls p
+WHERE p.id = pi.poll_id;
+
+-- instances that ended long ago are not finalized, their results are kept
+-- as they are
+UPDATE poll_instances
+SET status = 'closed'
+WHERE ends_at < NOW() - INTERVAL '1 day';
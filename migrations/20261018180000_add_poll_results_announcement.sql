@@ -0,0 +1,13 @@
+-- summary message posted once all the messages of a send are closed
+ALTER TABLE polls
+ADD COLUMN announce_results BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE polls
+ADD COLUMN results_template TEXT;
+
+-- the summary is posted where the poll was when not set
+ALTER TABLE polls
+ADD COLUMN results_channel_id BIGINT;
+
+ALTER TABLE poll_instances
+ADD COLUMN announced_at TIMESTAMPTZ;
@@ -0,0 +1,21 @@
+-- one occurrence per send of a poll, grouping the discord messages its
+-- answers were split into
+CREATE TABLE poll_occurrences (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id TEXT NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    sent_at BIGINT NOT NULL,
+    UNIQUE (poll_id, sent_at)
+);
+
+ALTER TABLE poll_instances
+ADD COLUMN occurrence_id BIGINT REFERENCES poll_occurrences(id) ON DELETE CASCADE;
+
+INSERT INTO poll_occurrences (poll_id, sent_at)
+SELECT DISTINCT poll_id, sent_at
+FROM poll_instances
+WHERE poll_id IS NOT NULL AND sent_at IS NOT NULL;
+
+UPDATE poll_instances pi
+SET occurrence_id = po.id
+FROM poll_occurrences po
+WHERE po.poll_id = pi.poll_id AND po.sent_at = pi.sent_at;
@@ -0,0 +1,14 @@
+-- sends requested through the api, out of the poll schedule
+CREATE TABLE poll_send_requests (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id TEXT NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- set once a sender has picked the request up
+    processed_at TIMESTAMPTZ
+);
+
+CREATE INDEX poll_send_requests_pending_idx ON poll_send_requests (requested_at)
+WHERE processed_at IS NULL;
+
+ALTER TABLE poll_instances
+ADD COLUMN manual BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- ledger of the scheduled sends, a row is claimed by a sender before posting
+-- the poll so that each occurrence is sent once whatever the number of
+-- senders running
+CREATE TABLE poll_sends (
+    poll_id TEXT NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    scheduled_at TIMESTAMPTZ NOT NULL,
+    status TEXT NOT NULL DEFAULT 'claimed',
+    claimed_by TEXT NOT NULL,
+    claimed_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ,
+    PRIMARY KEY (poll_id, scheduled_at)
+);
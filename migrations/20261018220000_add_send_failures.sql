@@ -0,0 +1,25 @@
+ALTER TABLE poll_sends
+ADD COLUMN attempts INT NOT NULL DEFAULT 1;
+
+-- set while the send waits to be retried
+ALTER TABLE poll_sends
+ADD COLUMN next_attempt_at TIMESTAMPTZ;
+
+CREATE INDEX poll_sends_retry_idx ON poll_sends (next_attempt_at)
+WHERE status = 'retrying';
+
+CREATE TABLE send_failures (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id TEXT NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    scheduled_at TIMESTAMPTZ NOT NULL,
+    attempt INT NOT NULL,
+    error TEXT NOT NULL,
+    -- set when the error comes from discord
+    discord_code BIGINT,
+    http_status INT,
+    failed_at TIMESTAMPTZ NOT NULL,
+    -- not set when the send was given up
+    retry_at TIMESTAMPTZ
+);
+
+CREATE INDEX send_failures_poll_id_idx ON send_failures (poll_id, failed_at);
@@ -0,0 +1,14 @@
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- start of the key, shown to tell the keys apart
+    prefix TEXT NOT NULL,
+    -- sha-256 of the key, the key itself is never stored
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    -- empty when the key is allowed on every guild
+    guild_ids BIGINT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
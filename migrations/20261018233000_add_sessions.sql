@@ -0,0 +1,12 @@
+CREATE TABLE sessions (
+    -- sha-256 of the session cookie
+    token_hash TEXT PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    username TEXT NOT NULL,
+    -- guilds the user manages, known at login
+    guild_ids BIGINT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
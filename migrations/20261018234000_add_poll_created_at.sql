@@ -0,0 +1,5 @@
+-- existing polls are considered created now
+ALTER TABLE polls
+ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX polls_created_at_idx ON polls (created_at, id);
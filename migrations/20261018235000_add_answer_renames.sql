@@ -0,0 +1,10 @@
+-- previous names of the answers, votes sent under them are counted for the
+-- renamed answer in the statistics
+CREATE TABLE answer_renames (
+    id BIGSERIAL PRIMARY KEY,
+    answer_id INT NOT NULL REFERENCES answers(id) ON DELETE CASCADE,
+    previous_answer TEXT NOT NULL,
+    renamed_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX answer_renames_answer_id_idx ON answer_renames (answer_id);
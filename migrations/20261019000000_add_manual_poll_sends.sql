@@ -0,0 +1,16 @@
+-- sends requested through the api go through the ledger as well, keyed by
+-- the date they were requested at
+ALTER TABLE poll_sends
+ADD COLUMN manual BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- occurrences are keyed by their send rather than by the second their first
+-- message was posted at, two sends posted within the same second are kept
+-- apart
+ALTER TABLE poll_occurrences
+ADD COLUMN scheduled_at TIMESTAMPTZ;
+
+UPDATE poll_occurrences SET scheduled_at = to_timestamp(sent_at);
+
+ALTER TABLE poll_occurrences ALTER COLUMN scheduled_at SET NOT NULL;
+ALTER TABLE poll_occurrences DROP CONSTRAINT poll_occurrences_poll_id_sent_at_key;
+ALTER TABLE poll_occurrences ADD UNIQUE (poll_id, scheduled_at);
@@ -1,2 +1,7 @@
+pub mod auth;
 pub mod dto;
 pub mod handlers;
+pub mod openapi;
+pub mod routes;
+pub mod state;
+pub mod validation;
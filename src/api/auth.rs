@@ -0,0 +1,221 @@
+use crate::auth::api_key_use_cases::ApiKeyUseCases;
+use crate::auth::domain::{Caller, Scope};
+use crate::auth::session_use_cases::SessionUseCases;
+use crate::poll::error::PollError;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::{header, request::Parts, HeaderMap};
+use chrono::Utc;
+use sqlx::PgPool;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+pub const SESSION_COOKIE: &str = "session";
+// random value checked when discord redirects back to the api
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+
+// Scope a handler requires from the api key of the request.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub struct PollsRead;
+pub struct PollsWrite;
+pub struct ResultsRead;
+pub struct Admin;
+
+impl RequiredScope for PollsRead {
+    const SCOPE: Scope = Scope::PollsRead;
+}
+
+impl RequiredScope for PollsWrite {
+    const SCOPE: Scope = Scope::PollsWrite;
+}
+
+impl RequiredScope for ResultsRead {
+    const SCOPE: Scope = Scope::ResultsRead;
+}
+
+impl RequiredScope for Admin {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+// Caller of the request, authenticated with an api key or else with a
+// session cookie. The request is rejected unless the caller has the scope S.
+pub struct Authorized<S> {
+    pub caller: Caller,
+    scope: PhantomData<S>,
+}
+
+impl<S> Deref for Authorized<S> {
+    type Target = Caller;
+
+    fn deref(&self) -> &Caller {
+        &self.caller
+    }
+}
+
+impl<S, T> FromRequestParts<T> for Authorized<S>
+where
+    S: RequiredScope,
+    PgPool: FromRef<T>,
+    T: Send + Sync,
+{
+    type Rejection = PollError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &T) -> Result<Self, Self::Rejection> {
+        let pool = PgPool::from_ref(state);
+
+        let caller = if let Some(key) = bearer_token(parts) {
+            Caller::ApiKey(
+                ApiKeyUseCases::new(&pool)
+                    .authenticate(key, Utc::now())
+                    .await?,
+            )
+        } else if let Some(token) = cookie(&parts.headers, SESSION_COOKIE) {
+            Caller::User(
+                SessionUseCases::new(&pool)
+                    .authenticate(token, Utc::now())
+                    .await?,
+            )
+        } else {
+            return Err(PollError::Unauthorized(String::from(
+                "missing api key or session",
+            )));
+        };
+
+        if !caller.has_scope(S::SCOPE) {
+            return Err(PollError::Forbidden(format!(
+                "missing the {} scope",
+                S::SCOPE
+            )));
+        }
+
+        Ok(Authorized {
+            caller,
+            scope: PhantomData,
+        })
+    }
+}
+
+// Key of an `Authorization: Bearer <key>` header.
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    let value = parts.headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    Some(token.trim()).filter(|token| !token.is_empty())
+}
+
+// Value of the cookie, cookies may be sent in several headers.
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+// Set-Cookie header value, a max age of 0 removes the cookie.
+pub fn set_cookie(name: &str, value: &str, max_age: i64, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        name, value, max_age
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+// Polls of guilds the caller is not allowed on are created or moved there
+// on purpose, they are refused rather than hidden.
+pub fn check_guild(caller: &Caller, guild_id: Option<i64>) -> Result<(), PollError> {
+    if caller.allows_guild(guild_id) {
+        return Ok(());
+    }
+
+    Err(PollError::Forbidden(match guild_id {
+        Some(guild_id) => format!("not allowed on guild {}", guild_id),
+        None => String::from("restricted to guilds, guild_id must be set"),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::domain::ApiKey;
+    use axum::http::{HeaderValue, Request};
+
+    fn parts(authorization: Option<&str>) -> Parts {
+        let mut request = Request::builder();
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        request.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(Some("key"), bearer_token(&parts(Some("Bearer key"))));
+        assert_eq!(Some("key"), bearer_token(&parts(Some("bearer  key"))));
+        assert_eq!(None, bearer_token(&parts(Some("Basic key"))));
+        assert_eq!(None, bearer_token(&parts(Some("Bearer "))));
+        assert_eq!(None, bearer_token(&parts(Some("key"))));
+        assert_eq!(None, bearer_token(&parts(None)));
+    }
+
+    #[test]
+    fn test_check_guild() {
+        let key = Caller::ApiKey(ApiKey::new(
+            String::from("test"),
+            "key",
+            vec![Scope::PollsWrite],
+            vec![1],
+        ));
+
+        assert!(check_guild(&key, Some(1)).is_ok());
+        assert!(matches!(
+            check_guild(&key, Some(2)),
+            Err(PollError::Forbidden(_))
+        ));
+        assert!(matches!(
+            check_guild(&key, None),
+            Err(PollError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_cookie() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, cookie(&headers, SESSION_COOKIE));
+
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; session=abc"));
+        headers.append(
+            header::COOKIE,
+            HeaderValue::from_static("oauth_state=def; b="),
+        );
+        assert_eq!(Some("abc"), cookie(&headers, SESSION_COOKIE));
+        assert_eq!(Some("def"), cookie(&headers, OAUTH_STATE_COOKIE));
+        assert_eq!(None, cookie(&headers, "b"));
+        assert_eq!(None, cookie(&headers, "sess"));
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        assert_eq!(
+            "session=abc; Path=/; HttpOnly; SameSite=Lax; Max-Age=60; Secure",
+            set_cookie(SESSION_COOKIE, "abc", 60, true)
+        );
+        assert_eq!(
+            "session=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0",
+            set_cookie(SESSION_COOKIE, "", 0, false)
+        );
+    }
+}
@@ -1,7 +1,19 @@
+use crate::auth::domain::{ApiKey as DomainApiKey, Scope, Session as DomainSession};
+use crate::guild::domain::{Channel as DomainChannel, Guild as DomainGuild};
+use crate::poll::domain::{
+    MisfirePolicy, Poll as DomainPoll, PollInstance as DomainPollInstance, PollInstanceStatus,
+    PollOccurrence as DomainPollOccurrence, PollSendFailure as DomainPollSendFailure,
+    PollSendRequest as DomainPollSendRequest,
+};
+use crate::poll::error::FieldError;
+use crate::poll::search::PollPage as DomainPollPage;
+use crate::poll::stats::{AnswerRename as DomainAnswerRename, PollStats as DomainPollStats};
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
 pub struct Poll {
     pub id: Uuid,
     pub cron: String,
@@ -10,33 +22,487 @@ pub struct Poll {
     pub multiselect: bool,
     pub guild: String,
     pub channel: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
     pub duration: i32,
     pub onetime: bool,
+    pub sent: bool,
+    pub timezone: String,
+    pub misfire_policy: MisfirePolicy,
+    pub enabled: bool,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub start_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub max_occurrences: Option<i32>,
+    pub occurrences_sent: i32,
+    pub remaining_occurrences: Option<i32>,
+    pub expose_voters: bool,
+    pub announce_results: bool,
+    pub results_template: Option<String>,
+    pub results_channel_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl From<DomainPoll> for Poll {
+    fn from(p: DomainPoll) -> Self {
+        let remaining_occurrences = p.remaining_occurrences();
+        Poll {
+            id: p.id,
+            cron: p.cron,
+            question: p.question,
+            answers: p.answers,
+            multiselect: p.multiselect,
+            guild: p.guild,
+            channel: p.channel,
+            guild_id: p.guild_id,
+            channel_id: p.channel_id,
+            duration: p.duration,
+            onetime: p.onetime,
+            sent: p.sent,
+            timezone: p.timezone,
+            misfire_policy: p.misfire_policy,
+            enabled: p.enabled,
+            paused_until: p.paused_until,
+            start_at: p.start_at,
+            end_at: p.end_at,
+            max_occurrences: p.max_occurrences,
+            occurrences_sent: p.occurrences_sent,
+            remaining_occurrences,
+            expose_voters: p.expose_voters,
+            announce_results: p.announce_results,
+            results_template: p.results_template,
+            results_channel_id: p.results_channel_id,
+            created_at: p.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct PollsQuery {
+    // name or id of the guild
+    pub guild: Option<String>,
+    // name or id of the channel
+    pub channel: Option<String>,
+    pub onetime: Option<bool>,
+    pub sent: Option<bool>,
+    // text searched in the questions
+    pub q: Option<String>,
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct PollPage {
+    pub polls: Vec<Poll>,
+    // given back as the cursor of the query to get the next page, none on the
+    // last one
+    pub next_cursor: Option<String>,
+}
+
+impl From<DomainPollPage> for PollPage {
+    fn from(page: DomainPollPage) -> Self {
+        PollPage {
+            polls: page.polls.into_iter().map(Poll::from).collect(),
+            next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
 pub struct CreatePoll {
     pub cron: String,
     pub question: String,
     pub answers: Vec<String>,
     pub multiselect: bool,
+    // either the ids or the names of the target have to be given, names are
+    // resolved to ids on creation
+    #[serde(default)]
     pub guild: String,
+    #[serde(default)]
     pub channel: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
     pub duration: i32,
     pub onetime: bool,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    pub start_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub max_occurrences: Option<i32>,
+    // voters are only listed by the api for polls opting in
+    #[serde(default)]
+    pub expose_voters: bool,
+    // a summary of the results is posted once every message of a send is
+    // closed, in the poll channel unless another one is given
+    #[serde(default)]
+    pub announce_results: bool,
+    pub results_template: Option<String>,
+    pub results_channel_id: Option<i64>,
 }
 
-pub type UpdatePoll = CreatePoll;
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct UpdatePoll {
+    #[serde(flatten)]
+    pub poll: CreatePoll,
+    // answers renamed rather than replaced, their votes keep counting for
+    // them in the statistics
+    #[serde(default)]
+    pub renames: Vec<AnswerRename>,
+}
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct AnswerRename {
+    pub from: String,
+    pub to: String,
+}
+
+impl From<AnswerRename> for DomainAnswerRename {
+    fn from(r: AnswerRename) -> Self {
+        DomainAnswerRename {
+            from: r.from,
+            to: r.to,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct PauseQuery {
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct ScheduleQuery {
+    pub count: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct SchedulePreview {
+    pub cron: String,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    pub count: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct Schedule {
+    pub timezone: String,
+    pub occurrences: Vec<DateTime<FixedOffset>>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
 pub struct PollInstance {
     pub id: i64,
     pub sent_at: i64,
     pub answers: Vec<PollInstanceAnswer>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub status: PollInstanceStatus,
+    // the answers votes are final once set
+    pub finalized_at: Option<DateTime<Utc>>,
+    pub occurrence_id: Option<i64>,
+    pub manual: bool,
+}
+
+impl From<DomainPollInstance> for PollInstance {
+    fn from(i: DomainPollInstance) -> Self {
+        PollInstance {
+            answers: i
+                .answers
+                .iter()
+                .map(|a| PollInstanceAnswer {
+                    answer: a.answer.clone(),
+                    votes: a.votes,
+                })
+                .collect(),
+            id: i.id,
+            sent_at: i.sent_at,
+            ends_at: i.ends_at,
+            status: i.status,
+            finalized_at: i.finalized_at,
+            occurrence_id: i.occurrence_id,
+            manual: i.manual,
+        }
+    }
+}
+
+// A send of a poll, the answers of all its discord messages are merged.
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct PollOccurrence {
+    pub id: i64,
+    pub sent_at: i64,
+    pub answers: Vec<PollInstanceAnswer>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub status: PollInstanceStatus,
+    pub finalized_at: Option<DateTime<Utc>>,
+    pub manual: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl From<DomainPollOccurrence> for PollOccurrence {
+    fn from(o: DomainPollOccurrence) -> Self {
+        PollOccurrence {
+            answers: o
+                .answers()
+                .into_iter()
+                .map(|a| PollInstanceAnswer {
+                    answer: a.answer,
+                    votes: a.votes,
+                })
+                .collect(),
+            ends_at: o.ends_at(),
+            status: o.status(),
+            finalized_at: o.finalized_at(),
+            manual: o.is_manual(),
+            id: o.id,
+            sent_at: o.sent_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
 pub struct PollInstanceAnswer {
     pub answer: String,
     pub votes: i32,
 }
+
+// The sender picks the request up on its next tick.
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct PollSendRequest {
+    pub id: i64,
+    pub poll_id: Uuid,
+    pub requested_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+impl From<DomainPollSendRequest> for PollSendRequest {
+    fn from(r: DomainPollSendRequest) -> Self {
+        PollSendRequest {
+            id: r.id,
+            poll_id: r.poll_id,
+            requested_at: r.requested_at,
+            processed_at: r.processed_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct SendFailure {
+    // none for a send left claimed, by a sender that stopped while posting it
+    pub id: Option<i64>,
+    pub scheduled_at: DateTime<Utc>,
+    pub attempt: i32,
+    pub error: String,
+    pub discord_code: Option<i64>,
+    pub http_status: Option<i32>,
+    pub failed_at: DateTime<Utc>,
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+impl From<DomainPollSendFailure> for SendFailure {
+    fn from(f: DomainPollSendFailure) -> Self {
+        SendFailure {
+            id: f.id,
+            scheduled_at: f.scheduled_at,
+            attempt: f.attempt,
+            error: f.error,
+            discord_code: f.discord_code,
+            http_status: f.http_status,
+            failed_at: f.failed_at,
+            retry_at: f.retry_at,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    // only the sends from that date, included
+    pub from: Option<DateTime<Utc>>,
+    // only the sends until that date, excluded
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct AnswerStats {
+    pub answer: String,
+    // names the answer had when it was sent, before being renamed
+    pub previous_answers: Vec<String>,
+    pub total_votes: i64,
+    pub mean_votes: f64,
+    // share of the votes of the poll, between 0 and 1
+    pub share: f64,
+    // sends the answer had the most votes in, ties included
+    pub wins: i64,
+    // sends the answer was part of
+    pub instances: i64,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct PollStats {
+    pub poll_id: Uuid,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // sends of the poll
+    pub occurrences: i64,
+    pub total_votes: i64,
+    pub answers: Vec<AnswerStats>,
+}
+
+impl PollStats {
+    pub fn new(
+        poll_id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        stats: DomainPollStats,
+    ) -> PollStats {
+        PollStats {
+            poll_id,
+            from,
+            to,
+            occurrences: stats.occurrences,
+            total_votes: stats.total_votes(),
+            answers: stats
+                .answers
+                .iter()
+                .map(|a| AnswerStats {
+                    answer: a.answer.clone(),
+                    previous_answers: a.previous_answers.clone(),
+                    total_votes: a.total_votes,
+                    mean_votes: a.mean_votes(),
+                    share: stats.share(a),
+                    wins: a.wins,
+                    instances: a.instances,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct AnswerVoters {
+    pub answer: String,
+    pub votes: i32,
+    pub voters: Vec<i64>,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+pub struct Error {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct Guild {
+    pub id: i64,
+    pub name: String,
+}
+
+impl From<DomainGuild> for Guild {
+    fn from(g: DomainGuild) -> Self {
+        Guild {
+            id: g.id,
+            name: g.name,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct Channel {
+    pub id: i64,
+    pub name: String,
+    pub kind: String,
+    pub parent_id: Option<i64>,
+}
+
+impl From<DomainChannel> for Channel {
+    fn from(c: DomainChannel) -> Self {
+        Channel {
+            id: c.id,
+            name: c.name,
+            kind: c.kind,
+            parent_id: c.parent_id,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct CreateApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    // the key is allowed on every guild when empty
+    #[serde(default)]
+    pub guild_ids: Vec<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub guild_ids: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<DomainApiKey> for ApiKey {
+    fn from(k: DomainApiKey) -> Self {
+        ApiKey {
+            id: k.id,
+            name: k.name,
+            prefix: k.prefix,
+            scopes: k.scopes,
+            guild_ids: k.guild_ids,
+            created_at: k.created_at,
+            last_used_at: k.last_used_at,
+            revoked_at: k.revoked_at,
+        }
+    }
+}
+
+// The key itself is only returned when it is created.
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+// Sent back by discord once the user authorized the api, or refused to.
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Debug)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub guild_ids: Vec<i64>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<DomainSession> for Session {
+    fn from(s: DomainSession) -> Self {
+        Session {
+            user_id: s.user_id,
+            username: s.username,
+            guild_ids: s.guild_ids,
+            expires_at: s.expires_at,
+        }
+    }
+}
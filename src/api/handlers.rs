@@ -1,23 +1,141 @@
-use crate::api::dto::{CreatePoll, Poll, PollInstance, PollInstanceAnswer, UpdatePoll};
+use crate::api::auth::{
+    check_guild, cookie, set_cookie, Admin, Authorized, PollsRead, PollsWrite, ResultsRead,
+    OAUTH_STATE_COOKIE, SESSION_COOKIE,
+};
+use crate::api::dto::{
+    AnswerVoters, ApiKey, Channel, CreateApiKey, CreatePoll, CreatedApiKey, Error as ErrorBody,
+    Guild, OAuthCallback, PauseQuery, Poll, PollInstance, PollInstanceAnswer, PollOccurrence,
+    PollPage, PollSendRequest, PollStats, PollsQuery, Schedule, SchedulePreview, ScheduleQuery,
+    SendFailure, Session, StatsQuery, UpdatePoll,
+};
+use crate::api::validation::{
+    validate_api_key, validate_channel_name, validate_channels, validate_pause, validate_poll,
+    validate_polls_query, validate_renames, validate_schedule_preview, validate_schedule_query,
+    validate_stats_query,
+};
+use crate::auth::api_key_use_cases::ApiKeyUseCases;
+use crate::auth::domain::{generate_key, Caller};
+use crate::auth::oauth::OAuthClient;
+use crate::auth::session_use_cases::{SessionUseCases, SESSION_DURATION_SECONDS};
+use crate::guild::guild_use_cases::GuildUseCases;
+use crate::poll::cron_filter;
 use crate::poll::domain::Poll as DomainPoll;
+use crate::poll::error::PollError;
 use crate::poll::poll_instance_use_cases::PollUseCases;
-use axum::{extract::Path, extract::State, http::StatusCode, response::IntoResponse, Json};
+use crate::poll::stats::AnswerRename as DomainAnswerRename;
+use axum::{
+    extract::Path,
+    extract::Query,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serenity::http::Http;
 use sqlx::PgPool;
-use std::error::Error;
+use std::sync::Arc;
 use uuid::Uuid;
 
-fn handle_error(e: Box<dyn Error>) -> StatusCode {
-    println!("{:?}", e);
-    StatusCode::INTERNAL_SERVER_ERROR
+impl IntoResponse for PollError {
+    fn into_response(self) -> Response {
+        let (status, code) = match &self {
+            PollError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            PollError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            PollError::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden"),
+            PollError::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, "validation"),
+            PollError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+            PollError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database"),
+            PollError::Discord(_) => (StatusCode::BAD_GATEWAY, "discord"),
+        };
+
+        // internal errors are logged, not exposed
+        let message = match &self {
+            PollError::Database(_) | PollError::Discord(_) => {
+                eprintln!("{:?}", self);
+                String::from("internal error")
+            }
+            e => e.to_string(),
+        };
+
+        let errors = match self {
+            PollError::Validation(errors) => errors,
+            _ => Vec::new(),
+        };
+
+        let body = ErrorBody {
+            code: code.to_string(),
+            message,
+            errors,
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+// Loads the poll, polls of guilds the caller is not allowed on are not
+// found.
+async fn find_poll(
+    poll_use_cases: &PollUseCases<'_>,
+    caller: &Caller,
+    id: Uuid,
+) -> Result<DomainPoll, PollError> {
+    let poll = poll_use_cases.get_poll_by_id(id).await?;
+    if !caller.allows_guild(poll.guild_id) {
+        return Err(PollError::not_found("poll", id));
+    }
+
+    Ok(poll)
+}
+
+// Ids of the channel the poll is posted into. Names are resolved once, so
+// that renaming the channel does not break the poll. The channels given by id
+// are checked to be channels of the guild.
+async fn resolve_target(pool: &PgPool, payload: &CreatePoll) -> Result<(i64, i64), PollError> {
+    let guild_use_cases = GuildUseCases::new(pool);
+
+    let (guild_id, channel_id) = match (payload.guild_id, payload.channel_id) {
+        (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+        _ => {
+            let channels = guild_use_cases
+                .find_channels_by_name(&payload.guild, &payload.channel)
+                .await?;
+            let channel = validate_channel_name(&payload.guild, &payload.channel, channels)?;
+            (channel.guild_id, channel.id)
+        }
+    };
+
+    let targets = [
+        ("channel_id", Some(channel_id)),
+        ("results_channel_id", payload.results_channel_id),
+    ];
+    let ids: Vec<i64> = targets.iter().filter_map(|(_, id)| *id).collect();
+    let channels = guild_use_cases.find_channels_by_ids(&ids).await?;
+    validate_channels(guild_id, &targets, &channels)?;
+
+    Ok((guild_id, channel_id))
 }
 
 // handlers
+#[utoipa::path(
+    post,
+    path = "/polls",
+    tag = "polls",
+    request_body = CreatePoll,
+    responses(
+        (status = 201, description = "id of the created poll", body = Uuid),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
 pub async fn create_poll(
+    auth: Authorized<PollsWrite>,
     State(pool): State<PgPool>,
     Json(payload): Json<CreatePoll>,
-) -> impl IntoResponse {
-    // TODO: input validation
-    println!("payload : {:?}", payload);
+) -> Result<impl IntoResponse, PollError> {
+    validate_poll(&payload)?;
+    let (guild_id, channel_id) = resolve_target(&pool, &payload).await?;
+    check_guild(&auth, Some(guild_id))?;
 
     let poll = DomainPoll::new()
         .cron(payload.cron)
@@ -26,151 +144,477 @@ pub async fn create_poll(
         .multiselect(payload.multiselect)
         .guild(payload.guild)
         .channel(payload.channel)
+        .guild_id(Some(guild_id))
+        .channel_id(Some(channel_id))
         .duration(payload.duration)
-        .onetime(payload.onetime);
+        .onetime(payload.onetime)
+        .timezone(payload.timezone)
+        .misfire_policy(payload.misfire_policy)
+        .start_at(payload.start_at)
+        .end_at(payload.end_at)
+        .max_occurrences(payload.max_occurrences)
+        .expose_voters(payload.expose_voters)
+        .announce_results(payload.announce_results)
+        .results_template(payload.results_template)
+        .results_channel_id(payload.results_channel_id);
 
-    println!("poll : {:?}", poll);
     let poll_use_cases = PollUseCases::new(&pool);
-    let (id, status) = match poll_use_cases.save_poll(poll).await {
-        Ok(id) => (Some(id), StatusCode::CREATED),
-        Err(e) => (None, handle_error(e)),
-    };
+    let id = poll_use_cases.save_poll(poll, &[]).await?;
 
-    if status == StatusCode::INTERNAL_SERVER_ERROR {
-        return status.into_response();
-    }
+    Ok((StatusCode::CREATED, Json(id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/polls",
+    tag = "polls",
+    params(PollsQuery),
+    responses(
+        (status = 200, description = "a page of polls", body = PollPage),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_polls(
+    auth: Authorized<PollsRead>,
+    Query(query): Query<PollsQuery>,
+    State(pool): State<PgPool>,
+) -> Result<Json<PollPage>, PollError> {
+    let mut filter = validate_polls_query(query)?;
+    filter.guild_ids = auth.guild_ids().map(<[i64]>::to_vec);
 
-    (status, Json(id)).into_response()
+    let poll_use_cases = PollUseCases::new(&pool);
+    let page = poll_use_cases.search_polls(filter).await?;
+
+    Ok(Json(PollPage::from(page)))
 }
 
-pub async fn get_polls(State(pool): State<PgPool>) -> Result<Json<Vec<Poll>>, StatusCode> {
-    let mut polls: Vec<Poll> = Vec::new();
+#[utoipa::path(
+    get,
+    path = "/polls/{id}",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "the poll", body = Poll),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll(
+    auth: Authorized<PollsRead>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Poll>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
-    let db_polls = poll_use_cases.get_polls().await.unwrap_or_else(|e| {
-        eprintln!("failed to read file: {e}");
-        Vec::new()
-    });
+    let poll = find_poll(&poll_use_cases, &auth, id).await?;
 
-    for p in db_polls {
-        polls.push(Poll {
-            id: p.id,
-            cron: p.cron,
-            question: p.question,
-            answers: p.answers,
-            multiselect: p.multiselect,
-            guild: p.guild,
-            channel: p.channel,
-            duration: p.duration,
-            onetime: p.onetime,
-        });
-    }
+    Ok(Json(Poll::from(poll)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/polls/{id}/pause",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll"), PauseQuery),
+    responses(
+        (status = 200, description = "the paused poll", body = Poll),
+        (status = 404, description = "poll not found", body = ErrorBody),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn pause_poll(
+    auth: Authorized<PollsWrite>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<PauseQuery>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Poll>, PollError> {
+    validate_pause(query.until, &Utc::now())?;
 
-    Ok(Json(polls))
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+    let poll = poll_use_cases.pause_poll(id, query.until).await?;
+
+    Ok(Json(Poll::from(poll)))
 }
 
-pub async fn get_poll(
+#[utoipa::path(
+    post,
+    path = "/polls/{id}/resume",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "the resumed poll", body = Poll),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn resume_poll(
+    auth: Authorized<PollsWrite>,
     Path(id): Path<Uuid>,
     State(pool): State<PgPool>,
-) -> Result<Json<Poll>, StatusCode> {
+) -> Result<Json<Poll>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
-    let poll = match poll_use_cases.get_poll_by_id(id).await {
-        Ok(p) => p,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+    find_poll(&poll_use_cases, &auth, id).await?;
+    let poll = poll_use_cases.resume_poll(id).await?;
 
-    Ok(Json(Poll {
-        id: poll.id,
-        cron: poll.cron,
-        question: poll.question,
-        answers: poll.answers,
-        multiselect: poll.multiselect,
-        guild: poll.guild,
-        channel: poll.channel,
-        duration: poll.duration,
-        onetime: poll.onetime,
-    }))
+    Ok(Json(Poll::from(poll)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/schedule",
+    tag = "schedule",
+    params(("id" = Uuid, Path, description = "id of the poll"), ScheduleQuery),
+    responses(
+        (status = 200, description = "next occurrences of the poll", body = Schedule),
+        (status = 404, description = "poll not found", body = ErrorBody),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll_schedule(
+    auth: Authorized<PollsRead>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ScheduleQuery>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Schedule>, PollError> {
+    let count = validate_schedule_query(query.count)?;
+
+    let poll_use_cases = PollUseCases::new(&pool);
+    let poll = find_poll(&poll_use_cases, &auth, id).await?;
+
+    schedule(&poll, count).map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/polls/schedule-preview",
+    tag = "schedule",
+    request_body = SchedulePreview,
+    responses(
+        (status = 200, description = "next occurrences of the cron expression", body = Schedule),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn preview_schedule(
+    _auth: Authorized<PollsRead>,
+    Json(payload): Json<SchedulePreview>,
+) -> Result<Json<Schedule>, PollError> {
+    let count = validate_schedule_preview(&payload)?;
+
+    let poll = DomainPoll::new()
+        .cron(payload.cron)
+        .timezone(payload.timezone);
+
+    schedule(&poll, count).map(Json)
+}
+
+fn schedule(poll: &DomainPoll, count: usize) -> Result<Schedule, PollError> {
+    let occurrences = cron_filter::upcoming(poll, &Utc::now(), count)?;
+
+    Ok(Schedule {
+        timezone: poll.timezone.clone(),
+        occurrences: occurrences.iter().map(|o| o.fixed_offset()).collect(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/instances",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "discord messages the poll was sent as", body = Vec<PollInstance>),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
 pub async fn get_poll_instances(
+    auth: Authorized<ResultsRead>,
     Path(id): Path<Uuid>,
     State(pool): State<PgPool>,
-) -> Result<Json<Vec<PollInstance>>, StatusCode> {
+) -> Result<Json<Vec<PollInstance>>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
 
-    let instances = match poll_use_cases.get_poll_instances_by_poll_id(id).await {
-        Ok(v) => v,
-        Err(e) => return Err(handle_error(e)),
-    };
+    let instances = poll_use_cases.get_poll_instances_by_poll_id(id).await?;
 
-    let mut results: Vec<PollInstance> = Vec::new();
-    for i in instances {
-        results.push(PollInstance {
-            answers: i
-                .answers
-                .iter()
-                .map(|a| {
-                    return PollInstanceAnswer {
-                        answer: a.answer.clone(),
-                        votes: a.votes,
-                    };
-                })
-                .collect(),
-            id: i.id,
-            sent_at: i.sent_at,
-        });
-    }
+    Ok(Json(
+        instances.into_iter().map(PollInstance::from).collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/occurrences",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "sends of the poll", body = Vec<PollOccurrence>),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll_occurrences(
+    auth: Authorized<ResultsRead>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<PollOccurrence>>, PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
 
-    Ok(Json(results))
+    let occurrences = poll_use_cases.get_poll_occurrences_by_poll_id(id).await?;
+
+    Ok(Json(
+        occurrences.into_iter().map(PollOccurrence::from).collect(),
+    ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/instances/{instance}",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll"), ("instance" = i64, Path, description = "id of the poll instance")),
+    responses(
+        (status = 200, description = "the poll instance", body = PollInstance),
+        (status = 404, description = "poll or instance not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
 pub async fn get_poll_instance(
+    auth: Authorized<ResultsRead>,
     Path((id, instance)): Path<(Uuid, i64)>,
     State(pool): State<PgPool>,
-) -> impl IntoResponse {
+) -> Result<Json<PollInstance>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
 
-    let instances = match poll_use_cases.get_poll_instances_by_poll_id(id).await {
-        Ok(v) => v,
-        Err(e) => return Err(handle_error(e)),
-    };
+    let instances = poll_use_cases.get_poll_instances_by_poll_id(id).await?;
 
     for i in instances {
         if i.id == instance {
-            return Ok(Json(PollInstance {
-                answers: i
-                    .answers
-                    .iter()
-                    .map(|a| {
-                        return PollInstanceAnswer {
-                            answer: a.answer.clone(),
-                            votes: a.votes,
-                        };
-                    })
-                    .collect(),
-                id: i.id,
-                sent_at: i.sent_at,
-            }));
+            return Ok(Json(PollInstance::from(i)));
         }
     }
 
-    Err(StatusCode::NOT_FOUND)
+    Err(PollError::not_found("poll instance", instance))
 }
 
-pub async fn delete_poll(Path(id): Path<Uuid>, State(pool): State<PgPool>) -> impl IntoResponse {
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/instances/{instance}/voters",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll"), ("instance" = i64, Path, description = "id of the poll instance")),
+    responses(
+        (status = 200, description = "voters of each answer", body = Vec<AnswerVoters>),
+        (status = 404, description = "poll or instance not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll_instance_voters(
+    auth: Authorized<ResultsRead>,
+    Path((id, instance)): Path<(Uuid, i64)>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<AnswerVoters>>, PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+
+    let poll = find_poll(&poll_use_cases, &auth, id).await?;
+    if !poll.expose_voters {
+        return Err(PollError::Forbidden(format!(
+            "voters of poll {} are not exposed",
+            id
+        )));
+    }
+
+    let i = poll_use_cases.get_poll_instance_by_id(instance).await?;
+    if i.poll_uuid != Some(id) {
+        return Err(PollError::not_found("poll instance", instance));
+    }
+
+    Ok(Json(
+        i.answers
+            .iter()
+            .map(|a| AnswerVoters {
+                answer: a.answer.clone(),
+                votes: a.votes,
+                voters: i.voters(a.discord_answer_id),
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/polls/{id}/instances/{instance}/reconcile",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll"), ("instance" = i64, Path, description = "id of the poll instance")),
+    responses(
+        (status = 200, description = "the instance with the votes fetched from discord", body = PollInstance),
+        (status = 404, description = "poll or instance not found", body = ErrorBody),
+        (status = 409, description = "channel of the instance is unknown", body = ErrorBody),
+        (status = 502, description = "discord could not be reached", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn reconcile_poll_instance(
+    auth: Authorized<PollsWrite>,
+    Path((id, instance)): Path<(Uuid, i64)>,
+    State(pool): State<PgPool>,
+    State(discord): State<Arc<Http>>,
+) -> Result<Json<PollInstance>, PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+
+    let i = poll_use_cases.get_poll_instance_by_id(instance).await?;
+    if i.poll_uuid != Some(id) {
+        return Err(PollError::not_found("poll instance", instance));
+    }
+
+    let i = poll_use_cases
+        .reconcile_instance(instance, discord.as_ref())
+        .await?;
+
+    Ok(Json(PollInstance::from(i)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/polls/{id}/instances/{instance}/close",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll"), ("instance" = i64, Path, description = "id of the poll instance")),
+    responses(
+        (status = 200, description = "instances of the send that were ended", body = Vec<PollInstance>),
+        (status = 404, description = "poll or instance not found", body = ErrorBody),
+        (status = 409, description = "the instance has already ended", body = ErrorBody),
+        (status = 502, description = "discord could not be reached", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn close_poll_instance(
+    auth: Authorized<PollsWrite>,
+    Path((id, instance)): Path<(Uuid, i64)>,
+    State(pool): State<PgPool>,
+    State(discord): State<Arc<Http>>,
+) -> Result<Json<Vec<PollInstance>>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
-    match poll_use_cases.delete_poll_by_id(id).await {
-        Ok(_) => StatusCode::OK,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    find_poll(&poll_use_cases, &auth, id).await?;
+
+    let i = poll_use_cases.get_poll_instance_by_id(instance).await?;
+    if i.poll_uuid != Some(id) {
+        return Err(PollError::not_found("poll instance", instance));
     }
+
+    let instances = poll_use_cases
+        .close_instance(instance, discord.as_ref(), Utc::now())
+        .await?;
+
+    Ok(Json(
+        instances.into_iter().map(PollInstance::from).collect(),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/polls/{id}/send",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 202, description = "the send request, picked up by the sender", body = PollSendRequest),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn send_poll(
+    auth: Authorized<PollsWrite>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<(StatusCode, Json<PollSendRequest>), PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+    let request = poll_use_cases.request_poll_send(id).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(PollSendRequest::from(request))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/failures",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "failed sends of the poll and the sends left claimed, last first", body = Vec<SendFailure>),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll_failures(
+    auth: Authorized<PollsRead>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<SendFailure>>, PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+    let failures = poll_use_cases
+        .get_poll_send_failures(id, Utc::now())
+        .await?;
+
+    Ok(Json(failures.into_iter().map(SendFailure::from).collect()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/polls/{id}",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "the poll was deleted"),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn delete_poll(
+    auth: Authorized<PollsWrite>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<StatusCode, PollError> {
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+    poll_use_cases.delete_poll_by_id(id).await?;
+    Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    put,
+    path = "/polls/{id}",
+    tag = "polls",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    request_body = UpdatePoll,
+    responses(
+        (status = 200, description = "the poll was updated"),
+        (status = 404, description = "poll not found", body = ErrorBody),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
 pub async fn update_poll(
+    auth: Authorized<PollsWrite>,
     Path(id): Path<Uuid>,
     State(pool): State<PgPool>,
     Json(payload): Json<UpdatePoll>,
-) -> impl IntoResponse {
-    // TODO: input validation
-    println!("payload : {:?}", payload);
+) -> Result<StatusCode, PollError> {
+    let UpdatePoll {
+        poll: payload,
+        renames,
+    } = payload;
+    validate_poll(&payload)?;
+    let (guild_id, channel_id) = resolve_target(&pool, &payload).await?;
+    check_guild(&auth, Some(guild_id))?;
+
+    let poll_use_cases = PollUseCases::new(&pool);
+    let saved = find_poll(&poll_use_cases, &auth, id).await?;
+    validate_renames(&renames, &saved.answers, &payload.answers)?;
 
     let poll = DomainPoll::new()
         .id(id)
@@ -180,30 +624,47 @@ pub async fn update_poll(
         .multiselect(payload.multiselect)
         .guild(payload.guild)
         .channel(payload.channel)
+        .guild_id(Some(guild_id))
+        .channel_id(Some(channel_id))
         .duration(payload.duration)
-        .onetime(payload.onetime);
-
-    println!("poll : {:?}", poll);
+        .onetime(payload.onetime)
+        .timezone(payload.timezone)
+        .misfire_policy(payload.misfire_policy)
+        .start_at(payload.start_at)
+        .end_at(payload.end_at)
+        .max_occurrences(payload.max_occurrences)
+        .expose_voters(payload.expose_voters)
+        .announce_results(payload.announce_results)
+        .results_template(payload.results_template)
+        .results_channel_id(payload.results_channel_id);
 
-    let poll_use_cases = PollUseCases::new(&pool);
-    match poll_use_cases.save_poll(poll).await {
-        Ok(_) => StatusCode::OK,
-        Err(e) => handle_error(e),
-    }
+    let renames: Vec<DomainAnswerRename> =
+        renames.into_iter().map(DomainAnswerRename::from).collect();
+    poll_use_cases.save_poll(poll, &renames).await?;
+    Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/instances/answers",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll")),
+    responses(
+        (status = 200, description = "answers of every instance of the poll", body = Vec<PollInstanceAnswer>),
+        (status = 404, description = "poll not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
 pub async fn get_answers_from_poll(
+    auth: Authorized<ResultsRead>,
     Path(id): Path<Uuid>,
     State(pool): State<PgPool>,
-) -> Result<Json<Vec<PollInstanceAnswer>>, StatusCode> {
+) -> Result<Json<Vec<PollInstanceAnswer>>, PollError> {
     let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
     let poll_instance_answers = poll_use_cases
         .get_poll_instance_answers_from_poll_id(id)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("failed to read file: {e}");
-            Vec::new()
-        });
+        .await?;
 
     let mut answers: Vec<PollInstanceAnswer> = Vec::new();
 
@@ -216,3 +677,313 @@ pub async fn get_answers_from_poll(
 
     Ok(Json(answers))
 }
+
+#[utoipa::path(
+    get,
+    path = "/polls/{id}/stats",
+    tag = "results",
+    params(("id" = Uuid, Path, description = "id of the poll"), StatsQuery),
+    responses(
+        (status = 200, description = "votes of each answer over the sends of the poll", body = PollStats),
+        (status = 404, description = "poll not found", body = ErrorBody),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_poll_stats(
+    auth: Authorized<ResultsRead>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<StatsQuery>,
+    State(pool): State<PgPool>,
+) -> Result<Json<PollStats>, PollError> {
+    validate_stats_query(query.from, query.to)?;
+
+    let poll_use_cases = PollUseCases::new(&pool);
+    find_poll(&poll_use_cases, &auth, id).await?;
+    let stats = poll_use_cases
+        .get_poll_stats(id, query.from, query.to)
+        .await?;
+
+    Ok(Json(PollStats::new(id, query.from, query.to, stats)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/guilds",
+    tag = "guilds",
+    responses(
+        (status = 200, description = "guilds of the bot", body = Vec<Guild>),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_guilds(
+    auth: Authorized<PollsRead>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<Guild>>, PollError> {
+    let guild_use_cases = GuildUseCases::new(&pool);
+    let guilds = guild_use_cases.get_guilds().await?;
+
+    Ok(Json(
+        guilds
+            .into_iter()
+            .filter(|g| auth.allows_guild(Some(g.id)))
+            .map(Guild::from)
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/guilds/{id}/channels",
+    tag = "guilds",
+    params(("id" = i64, Path, description = "id of the guild")),
+    responses(
+        (status = 200, description = "channels of the guild", body = Vec<Channel>),
+        (status = 404, description = "guild not found", body = ErrorBody),
+    ),
+    security(("api_key" = []), ("session" = [])),
+)]
+pub async fn get_guild_channels(
+    auth: Authorized<PollsRead>,
+    Path(id): Path<i64>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<Channel>>, PollError> {
+    if !auth.allows_guild(Some(id)) {
+        return Err(PollError::not_found("guild", id));
+    }
+
+    let guild_use_cases = GuildUseCases::new(&pool);
+    let channels = guild_use_cases.get_guild_channels(id).await?;
+
+    Ok(Json(channels.into_iter().map(Channel::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    tag = "api keys",
+    request_body = CreateApiKey,
+    responses(
+        (status = 201, description = "the key, only returned once", body = CreatedApiKey),
+        (status = 422, description = "invalid input", body = ErrorBody),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn create_api_key(
+    _auth: Authorized<Admin>,
+    State(pool): State<PgPool>,
+    Json(payload): Json<CreateApiKey>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), PollError> {
+    validate_api_key(&payload)?;
+
+    let api_key_use_cases = ApiKeyUseCases::new(&pool);
+    let (api_key, key) = api_key_use_cases
+        .create_api_key(payload.name, payload.scopes, payload.guild_ids)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKey {
+            api_key: ApiKey::from(api_key),
+            key,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    tag = "api keys",
+    responses(
+        (status = 200, description = "every api key", body = Vec<ApiKey>),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn get_api_keys(
+    _auth: Authorized<Admin>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<ApiKey>>, PollError> {
+    let api_key_use_cases = ApiKeyUseCases::new(&pool);
+    let api_keys = api_key_use_cases.get_api_keys().await?;
+
+    Ok(Json(api_keys.into_iter().map(ApiKey::from).collect()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api-keys/{id}",
+    tag = "api keys",
+    params(("id" = Uuid, Path, description = "id of the api key")),
+    responses(
+        (status = 200, description = "the key was revoked"),
+        (status = 404, description = "api key not found", body = ErrorBody),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn revoke_api_key(
+    _auth: Authorized<Admin>,
+    Path(id): Path<Uuid>,
+    State(pool): State<PgPool>,
+) -> Result<StatusCode, PollError> {
+    let api_key_use_cases = ApiKeyUseCases::new(&pool);
+    api_key_use_cases.revoke_api_key(id, Utc::now()).await?;
+    Ok(StatusCode::OK)
+}
+
+// the login has to be completed within that time
+const OAUTH_STATE_MAX_AGE: i64 = 60 * 10;
+
+fn oauth_client(oauth: Option<Arc<OAuthClient>>) -> Result<Arc<OAuthClient>, PollError> {
+    oauth.ok_or_else(|| PollError::NotFound(String::from("login with discord is not configured")))
+}
+
+// Redirects to discord, which redirects back to the callback once the user
+// authorized the api.
+#[utoipa::path(
+    get,
+    path = "/auth/login",
+    tag = "auth",
+    responses(
+        (status = 303, description = "redirects to discord", headers(("Location" = String, description = "authorization url of discord"))),
+        (status = 404, description = "login with discord is not configured", body = ErrorBody),
+    ),
+)]
+pub async fn login(
+    State(oauth): State<Option<Arc<OAuthClient>>>,
+) -> Result<impl IntoResponse, PollError> {
+    let oauth = oauth_client(oauth)?;
+    let state = generate_key();
+
+    Ok((
+        StatusCode::SEE_OTHER,
+        AppendHeaders([
+            (header::LOCATION, oauth.authorize_url(&state)),
+            (
+                header::SET_COOKIE,
+                set_cookie(
+                    OAUTH_STATE_COOKIE,
+                    &state,
+                    OAUTH_STATE_MAX_AGE,
+                    oauth.config.secure_cookies(),
+                ),
+            ),
+        ]),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/callback",
+    tag = "auth",
+    params(OAuthCallback),
+    responses(
+        (status = 200, description = "the session, kept in the session cookie", body = Session, headers(("Set-Cookie" = String, description = "session cookie"))),
+        (status = 401, description = "the login failed", body = ErrorBody),
+        (status = 404, description = "login with discord is not configured", body = ErrorBody),
+        (status = 502, description = "discord could not be reached", body = ErrorBody),
+    ),
+)]
+pub async fn oauth_callback(
+    Query(query): Query<OAuthCallback>,
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+    State(oauth): State<Option<Arc<OAuthClient>>>,
+) -> Result<impl IntoResponse, PollError> {
+    let oauth = oauth_client(oauth)?;
+
+    if let Some(error) = query.error {
+        return Err(PollError::Unauthorized(format!(
+            "authorization refused: {}",
+            error
+        )));
+    }
+
+    // the state proves the login was started from this browser
+    let state = cookie(&headers, OAUTH_STATE_COOKIE);
+    if state.is_none() || state != query.state.as_deref() {
+        return Err(PollError::Unauthorized(String::from("invalid oauth state")));
+    }
+    let code = query
+        .code
+        .ok_or_else(|| PollError::Unauthorized(String::from("missing authorization code")))?;
+
+    // roles are only looked up in the guilds of the bot
+    let guild_use_cases = GuildUseCases::new(&pool);
+    let bot_guild_ids: Vec<i64> = guild_use_cases
+        .get_guilds()
+        .await?
+        .iter()
+        .map(|g| g.id)
+        .collect();
+    let user = oauth.login(&code, &bot_guild_ids).await?;
+
+    let session_use_cases = SessionUseCases::new(&pool);
+    let (session, token) = session_use_cases
+        .create_session(user.id, user.username, user.guild_ids, Utc::now())
+        .await?;
+
+    let secure = oauth.config.secure_cookies();
+    Ok((
+        AppendHeaders([
+            (
+                header::SET_COOKIE,
+                set_cookie(SESSION_COOKIE, &token, SESSION_DURATION_SECONDS, secure),
+            ),
+            (
+                header::SET_COOKIE,
+                set_cookie(OAUTH_STATE_COOKIE, "", 0, secure),
+            ),
+        ]),
+        Json(Session::from(session)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/session",
+    tag = "auth",
+    responses(
+        (status = 200, description = "the session of the cookie", body = Session),
+        (status = 401, description = "missing or invalid session", body = ErrorBody),
+    ),
+)]
+pub async fn get_session(
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+) -> Result<Json<Session>, PollError> {
+    let token = cookie(&headers, SESSION_COOKIE)
+        .ok_or_else(|| PollError::Unauthorized(String::from("missing session")))?;
+
+    let session_use_cases = SessionUseCases::new(&pool);
+    let session = session_use_cases.authenticate(token, Utc::now()).await?;
+
+    Ok(Json(Session::from(session)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "the session was ended"),
+    ),
+)]
+pub async fn logout(
+    headers: HeaderMap,
+    State(pool): State<PgPool>,
+    State(oauth): State<Option<Arc<OAuthClient>>>,
+) -> Result<impl IntoResponse, PollError> {
+    if let Some(token) = cookie(&headers, SESSION_COOKIE) {
+        let session_use_cases = SessionUseCases::new(&pool);
+        session_use_cases.delete_session(token).await?;
+    }
+
+    let secure = oauth.is_some_and(|oauth| oauth.config.secure_cookies());
+    Ok((
+        StatusCode::OK,
+        AppendHeaders([(
+            header::SET_COOKIE,
+            set_cookie(SESSION_COOKIE, "", 0, secure),
+        )]),
+    ))
+}
@@ -0,0 +1,131 @@
+use crate::api::handlers;
+use utoipa::openapi::path::{Operation, PathItem};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Response, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+// Specification of the api, generated from the handlers and the dtos. It is
+// served at /openapi.json, the routes test checks it matches the router.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "cron-poll-discord",
+        description = "Discord polls sent on cron schedules, and their results"
+    ),
+    paths(
+        handlers::get_polls,
+        handlers::create_poll,
+        handlers::get_poll,
+        handlers::update_poll,
+        handlers::delete_poll,
+        handlers::preview_schedule,
+        handlers::get_poll_schedule,
+        handlers::pause_poll,
+        handlers::resume_poll,
+        handlers::send_poll,
+        handlers::get_poll_failures,
+        handlers::get_poll_instances,
+        handlers::get_poll_occurrences,
+        handlers::get_poll_instance,
+        handlers::get_poll_instance_voters,
+        handlers::reconcile_poll_instance,
+        handlers::close_poll_instance,
+        handlers::get_answers_from_poll,
+        handlers::get_poll_stats,
+        handlers::get_guilds,
+        handlers::get_guild_channels,
+        handlers::login,
+        handlers::oauth_callback,
+        handlers::get_session,
+        handlers::logout,
+        handlers::get_api_keys,
+        handlers::create_api_key,
+        handlers::revoke_api_key,
+    ),
+    modifiers(&Security, &CommonErrors),
+    tags(
+        (name = "polls", description = "Polls and their sends"),
+        (name = "schedule", description = "Occurrences of the cron expressions"),
+        (name = "results", description = "Messages the polls were sent as and their votes"),
+        (name = "guilds", description = "Guilds and channels of the bot"),
+        (name = "auth", description = "Login with discord"),
+        (name = "api keys", description = "Api keys, managed by admin keys"),
+    )
+)]
+pub struct ApiDoc;
+
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("Api key, sent as `Authorization: Bearer <key>`"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                "session",
+                "Session of a user logged in with discord",
+            ))),
+        );
+    }
+}
+
+// Errors any handler may return, they are documented once here rather than
+// on each handler.
+struct CommonErrors;
+
+impl Modify for CommonErrors {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for item in openapi.paths.paths.values_mut() {
+            for operation in operations(item) {
+                let responses = &mut operation.responses.responses;
+
+                if operation.security.is_some() {
+                    responses
+                        .entry(String::from("401"))
+                        .or_insert_with(|| error_response("missing or invalid credentials"));
+                    responses.entry(String::from("403")).or_insert_with(|| {
+                        error_response("missing scope, or guild the caller is not allowed on")
+                    });
+                }
+                responses
+                    .entry(String::from("500"))
+                    .or_insert_with(|| error_response("internal error"));
+            }
+        }
+    }
+}
+
+fn error_response(description: &str) -> RefOr<Response> {
+    ResponseBuilder::new()
+        .description(description)
+        .content(
+            "application/json",
+            ContentBuilder::new()
+                .schema(Some(Ref::from_schema_name("Error")))
+                .build(),
+        )
+        .build()
+        .into()
+}
+
+fn operations(item: &mut PathItem) -> impl Iterator<Item = &mut Operation> {
+    [
+        &mut item.get,
+        &mut item.post,
+        &mut item.put,
+        &mut item.patch,
+        &mut item.delete,
+    ]
+    .into_iter()
+    .filter_map(Option::as_mut)
+}
@@ -0,0 +1,230 @@
+use crate::api::handlers::{
+    close_poll_instance, create_api_key, create_poll, delete_poll, get_answers_from_poll,
+    get_api_keys, get_guild_channels, get_guilds, get_poll, get_poll_failures, get_poll_instance,
+    get_poll_instance_voters, get_poll_instances, get_poll_occurrences, get_poll_schedule,
+    get_poll_stats, get_polls, get_session, login, logout, oauth_callback, pause_poll,
+    preview_schedule, reconcile_poll_instance, resume_poll, revoke_api_key, send_poll, update_poll,
+};
+use crate::api::openapi::ApiDoc;
+use crate::api::state::AppState;
+use axum::routing::{delete, get, post, MethodRouter};
+use axum::Router;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+pub const DOCS_PATH: &str = "/docs";
+
+// Routes of the api, each of them is documented in the specification.
+fn routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/polls", get(get_polls).post(create_poll)),
+        (
+            "/polls/{id}",
+            get(get_poll).delete(delete_poll).put(update_poll),
+        ),
+        ("/polls/schedule-preview", post(preview_schedule)),
+        ("/polls/{id}/schedule", get(get_poll_schedule)),
+        ("/polls/{id}/pause", post(pause_poll)),
+        ("/polls/{id}/resume", post(resume_poll)),
+        ("/polls/{id}/send", post(send_poll)),
+        ("/polls/{id}/failures", get(get_poll_failures)),
+        ("/polls/{id}/instances", get(get_poll_instances)),
+        ("/polls/{id}/occurrences", get(get_poll_occurrences)),
+        ("/polls/{id}/instances/{instance}", get(get_poll_instance)),
+        (
+            "/polls/{id}/instances/{instance}/voters",
+            get(get_poll_instance_voters),
+        ),
+        (
+            "/polls/{id}/instances/{instance}/reconcile",
+            post(reconcile_poll_instance),
+        ),
+        (
+            "/polls/{id}/instances/{instance}/close",
+            post(close_poll_instance),
+        ),
+        ("/polls/{id}/instances/answers", get(get_answers_from_poll)),
+        ("/polls/{id}/stats", get(get_poll_stats)),
+        ("/guilds", get(get_guilds)),
+        ("/guilds/{id}/channels", get(get_guild_channels)),
+        ("/auth/login", get(login)),
+        ("/auth/callback", get(oauth_callback)),
+        ("/auth/session", get(get_session)),
+        ("/auth/logout", post(logout)),
+        ("/api-keys", get(get_api_keys).post(create_api_key)),
+        ("/api-keys/{id}", delete(revoke_api_key)),
+    ]
+}
+
+fn api_router() -> Router<AppState> {
+    routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, method_router)| {
+            router.route(path, method_router)
+        })
+}
+
+// The api along with its specification and the swagger ui to browse it.
+pub fn router(state: AppState) -> Router {
+    api_router()
+        .merge(SwaggerUi::new(DOCS_PATH).url(OPENAPI_PATH, ApiDoc::openapi()))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use reqwest::Method;
+    use serenity::http::Http;
+    use sqlx::postgres::PgPoolOptions;
+    use std::collections::BTreeSet;
+    use std::sync::Arc;
+    use utoipa::openapi::path::PathItem;
+
+    const METHODS: [Method; 5] = [
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+    ];
+
+    fn documented_methods(item: &PathItem) -> Vec<Method> {
+        let operations = [&item.get, &item.post, &item.put, &item.patch, &item.delete];
+
+        METHODS
+            .into_iter()
+            .zip(operations)
+            .filter(|(_, operation)| operation.is_some())
+            .map(|(method, _)| method)
+            .collect()
+    }
+
+    fn state() -> AppState {
+        AppState {
+            // requests without credentials never reach the database
+            pool: PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/polls")
+                .unwrap(),
+            discord: Arc::new(Http::new("token")),
+            oauth: None,
+        }
+    }
+
+    // Serves the api, requests matching no route are answered with a 418 so
+    // that they are told apart from the 404s of the handlers.
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = router.fallback(|| async { StatusCode::IM_A_TEAPOT });
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        format!("http://{}", address)
+    }
+
+    // path parameters are filled in, handlers reject the requests before
+    // parsing them
+    fn url(base: &str, path: &str) -> String {
+        let path: Vec<&str> = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "1"
+                } else {
+                    segment
+                }
+            })
+            .collect();
+
+        format!("{}{}", base, path.join("/"))
+    }
+
+    #[test]
+    fn test_routes_are_documented() {
+        let spec = ApiDoc::openapi();
+
+        let routed: BTreeSet<&str> = routes().into_iter().map(|(path, _)| path).collect();
+        let documented: BTreeSet<&str> = spec.paths.paths.keys().map(String::as_str).collect();
+
+        assert_eq!(documented, routed);
+    }
+
+    #[tokio::test]
+    async fn test_documented_methods_are_routed() {
+        let base = serve(api_router().with_state(state())).await;
+        let client = reqwest::Client::new();
+
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let documented = documented_methods(&item);
+            for method in METHODS {
+                let status = client
+                    .request(method.clone(), url(&base, &path))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status();
+
+                assert_ne!(418, status.as_u16(), "{} {} is not routed", method, path);
+                if documented.contains(&method) {
+                    assert_ne!(405, status.as_u16(), "{} {} is not routed", method, path);
+                } else {
+                    assert_eq!(
+                        405,
+                        status.as_u16(),
+                        "{} {} is not documented",
+                        method,
+                        path
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors_are_documented() {
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            for method in documented_methods(&item) {
+                let operation = match method {
+                    Method::GET => item.get.as_ref(),
+                    Method::POST => item.post.as_ref(),
+                    Method::PUT => item.put.as_ref(),
+                    Method::PATCH => item.patch.as_ref(),
+                    _ => item.delete.as_ref(),
+                }
+                .unwrap();
+                let statuses: Vec<&str> = operation
+                    .responses
+                    .responses
+                    .keys()
+                    .map(String::as_str)
+                    .collect();
+
+                assert!(statuses.contains(&"500"), "{} {}", method, path);
+                if operation.security.is_some() {
+                    assert!(statuses.contains(&"401"), "{} {}", method, path);
+                    assert!(statuses.contains(&"403"), "{} {}", method, path);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openapi_is_served() {
+        let base = serve(router(state())).await;
+
+        let spec = reqwest::get(format!("{}{}", base, OPENAPI_PATH))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(ApiDoc::openapi().to_json().unwrap(), spec);
+
+        let docs = reqwest::get(format!("{}{}/", base, DOCS_PATH))
+            .await
+            .unwrap();
+        assert_eq!(200, docs.status().as_u16());
+    }
+}
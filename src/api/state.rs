@@ -0,0 +1,33 @@
+use crate::auth::oauth::OAuthClient;
+use axum::extract::FromRef;
+use serenity::http::Http;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+// Handlers extract the part of the state they need, State<PgPool> for most
+// of them.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub discord: Arc<Http>,
+    // not set when the login with discord is not configured
+    pub oauth: Option<Arc<OAuthClient>>,
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Http> {
+    fn from_ref(state: &AppState) -> Self {
+        state.discord.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<OAuthClient>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.oauth.clone()
+    }
+}
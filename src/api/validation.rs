@@ -0,0 +1,899 @@
+use crate::api::dto::{AnswerRename, CreateApiKey, CreatePoll, PollsQuery, SchedulePreview};
+use crate::auth::domain::Scope;
+use crate::guild::domain::Channel;
+use crate::poll::cron_filter::parse_cron;
+use crate::poll::error::{FieldError, PollError};
+use crate::poll::search::{PollCursor, PollFilter, PollSort, MAX_PAGE_SIZE};
+use crate::poll::summary::{unknown_placeholders, MAX_MESSAGE_LENGTH};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+// limits enforced by discord on polls
+pub const MAX_QUESTION_LENGTH: usize = 300;
+pub const MAX_ANSWER_LENGTH: usize = 55;
+// discord polls last between 1 hour and 7 days, the duration is in seconds
+// and discord only counts whole hours
+pub const MIN_DURATION: i32 = 60 * 60;
+pub const MAX_DURATION: i32 = 60 * 60 * 24 * 7;
+// answers are sent in batches of 10 per discord poll, this caps the number of
+// messages a single poll can post
+pub const MAX_ANSWERS: usize = 50;
+pub const DEFAULT_SCHEDULE_COUNT: usize = 5;
+pub const MAX_SCHEDULE_COUNT: usize = 100;
+pub const MAX_API_KEY_NAME_LENGTH: usize = 100;
+
+// Checks every field of the payload, returning all the errors found rather
+// than stopping at the first one.
+pub fn validate_poll(payload: &CreatePoll) -> Result<(), PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    validate_schedule(&payload.cron, &payload.timezone, &mut errors);
+
+    if payload.question.trim().is_empty() {
+        errors.push(FieldError::new("question", "must not be empty".to_string()));
+    } else if payload.question.chars().count() > MAX_QUESTION_LENGTH {
+        errors.push(FieldError::new(
+            "question",
+            format!("must be at most {} characters", MAX_QUESTION_LENGTH),
+        ));
+    }
+
+    if payload.answers.is_empty() {
+        errors.push(FieldError::new(
+            "answers",
+            "must contain at least one answer".to_string(),
+        ));
+    } else if payload.answers.len() > MAX_ANSWERS {
+        errors.push(FieldError::new(
+            "answers",
+            format!("must contain at most {} answers", MAX_ANSWERS),
+        ));
+    }
+
+    let mut seen: HashSet<&str> = HashSet::new();
+    for (i, answer) in payload.answers.iter().enumerate() {
+        let field = format!("answers[{}]", i);
+
+        if answer.trim().is_empty() {
+            errors.push(FieldError::new(&field, "must not be empty".to_string()));
+        } else if answer.chars().count() > MAX_ANSWER_LENGTH {
+            errors.push(FieldError::new(
+                &field,
+                format!("must be at most {} characters", MAX_ANSWER_LENGTH),
+            ));
+        }
+
+        if !seen.insert(answer.as_str()) {
+            errors.push(FieldError::new(
+                &field,
+                format!("duplicate answer: {}", answer),
+            ));
+        }
+    }
+
+    // discord ids are positive, serenity panics on 0
+    for (field, id) in [
+        ("guild_id", payload.guild_id),
+        ("channel_id", payload.channel_id),
+        ("results_channel_id", payload.results_channel_id),
+    ] {
+        if id.is_some_and(|id| id <= 0) {
+            errors.push(FieldError::new(field, "must be positive".to_string()));
+        }
+    }
+
+    match (payload.guild_id, payload.channel_id) {
+        (Some(_), Some(_)) => (),
+        (None, None) => {
+            if payload.guild.trim().is_empty() || payload.channel.trim().is_empty() {
+                errors.push(FieldError::new(
+                    "channel",
+                    "either channel_id and guild_id or channel and guild must be set".to_string(),
+                ));
+            }
+        }
+        (None, Some(_)) => errors.push(FieldError::new(
+            "guild_id",
+            "must be set along with channel_id".to_string(),
+        )),
+        (Some(_), None) => errors.push(FieldError::new(
+            "channel_id",
+            "must be set along with guild_id".to_string(),
+        )),
+    }
+
+    if payload.duration < MIN_DURATION || payload.duration > MAX_DURATION {
+        errors.push(FieldError::new(
+            "duration",
+            format!(
+                "must be between {} and {} seconds (1 hour to 7 days)",
+                MIN_DURATION, MAX_DURATION
+            ),
+        ));
+    } else if payload.duration % MIN_DURATION != 0 {
+        errors.push(FieldError::new(
+            "duration",
+            format!("must be a multiple of {} seconds", MIN_DURATION),
+        ));
+    }
+
+    if let (Some(start_at), Some(end_at)) = (payload.start_at, payload.end_at) {
+        if end_at <= start_at {
+            errors.push(FieldError::new(
+                "end_at",
+                "must be after start_at".to_string(),
+            ));
+        }
+    }
+
+    if payload.max_occurrences.is_some_and(|max| max < 1) {
+        errors.push(FieldError::new(
+            "max_occurrences",
+            "must be at least 1".to_string(),
+        ));
+    }
+
+    if let Some(template) = &payload.results_template {
+        if template.trim().is_empty() {
+            errors.push(FieldError::new(
+                "results_template",
+                "must not be empty".to_string(),
+            ));
+        } else if template.chars().count() > MAX_MESSAGE_LENGTH {
+            errors.push(FieldError::new(
+                "results_template",
+                format!("must be at most {} characters", MAX_MESSAGE_LENGTH),
+            ));
+        }
+
+        for placeholder in unknown_placeholders(template) {
+            errors.push(FieldError::new(
+                "results_template",
+                format!("unknown placeholder: {{{}}}", placeholder),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PollError::Validation(errors))
+    }
+}
+
+pub fn validate_schedule_preview(payload: &SchedulePreview) -> Result<usize, PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    validate_schedule(&payload.cron, &payload.timezone, &mut errors);
+    let count = validate_schedule_count(payload.count, &mut errors);
+
+    if errors.is_empty() {
+        Ok(count)
+    } else {
+        Err(PollError::Validation(errors))
+    }
+}
+
+pub fn validate_schedule_query(count: Option<usize>) -> Result<usize, PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    let count = validate_schedule_count(count, &mut errors);
+
+    if errors.is_empty() {
+        Ok(count)
+    } else {
+        Err(PollError::Validation(errors))
+    }
+}
+
+pub fn validate_pause(until: Option<DateTime<Utc>>, now: &DateTime<Utc>) -> Result<(), PollError> {
+    match until {
+        Some(until) if until <= *now => Err(PollError::Validation(vec![FieldError::new(
+            "until",
+            "must be in the future".to_string(),
+        )])),
+        _ => Ok(()),
+    }
+}
+
+// The channel given by name, among the channels with that name the bot can
+// post into.
+pub fn validate_channel_name(
+    guild: &str,
+    channel: &str,
+    channels: Vec<Channel>,
+) -> Result<Channel, PollError> {
+    let message = match channels.len() {
+        1 => return Ok(channels.into_iter().next().unwrap()),
+        0 => format!(
+            "no channel found for guild {} and channel {}",
+            guild, channel
+        ),
+        _ => format!(
+            "multiple channels found for guild {} and channel {}, use channel_id",
+            guild, channel
+        ),
+    };
+
+    Err(PollError::Validation(vec![FieldError::new(
+        "channel", message,
+    )]))
+}
+
+// The channels the poll is posted into and its results announced in must be
+// channels of its guild the bot can post into.
+pub fn validate_channels(
+    guild_id: i64,
+    targets: &[(&str, Option<i64>)],
+    channels: &[Channel],
+) -> Result<(), PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    for (field, id) in targets {
+        let Some(id) = id else { continue };
+        if !channels
+            .iter()
+            .any(|c| c.id == *id && c.guild_id == guild_id)
+        {
+            errors.push(FieldError::new(
+                field,
+                format!("no channel {} found for guild {}", id, guild_id),
+            ));
+        }
+    }
+
+    if errors.len() > 0 {
+        return Err(PollError::Validation(errors));
+    }
+
+    Ok(())
+}
+
+// An answer is renamed from one of the saved answers the poll no longer has,
+// into one of its new answers.
+pub fn validate_renames(
+    renames: &[AnswerRename],
+    saved: &[String],
+    answers: &[String],
+) -> Result<(), PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    for (i, rename) in renames.iter().enumerate() {
+        if !saved.contains(&rename.from) || answers.contains(&rename.from) {
+            errors.push(FieldError::new(
+                "renames",
+                format!("{} is not an answer removed from the poll", rename.from),
+            ));
+        }
+
+        if saved.contains(&rename.to) || !answers.contains(&rename.to) {
+            errors.push(FieldError::new(
+                "renames",
+                format!("{} is not an answer added to the poll", rename.to),
+            ));
+        }
+
+        if renames[..i]
+            .iter()
+            .any(|r| r.from == rename.from || r.to == rename.to)
+        {
+            errors.push(FieldError::new(
+                "renames",
+                format!("{} is renamed more than once", rename.from),
+            ));
+        }
+    }
+
+    if errors.len() > 0 {
+        return Err(PollError::Validation(errors));
+    }
+
+    Ok(())
+}
+
+pub fn validate_stats_query(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<(), PollError> {
+    match (from, to) {
+        (Some(from), Some(to)) if to <= from => Err(PollError::Validation(vec![FieldError::new(
+            "to",
+            "must be after from".to_string(),
+        )])),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_api_key(payload: &CreateApiKey) -> Result<(), PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+
+    if payload.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty".to_string()));
+    } else if payload.name.chars().count() > MAX_API_KEY_NAME_LENGTH {
+        errors.push(FieldError::new(
+            "name",
+            format!("must be at most {} characters", MAX_API_KEY_NAME_LENGTH),
+        ));
+    }
+
+    if payload.scopes.is_empty() {
+        errors.push(FieldError::new(
+            "scopes",
+            "must contain at least one scope".to_string(),
+        ));
+    }
+
+    // admin keys create the other keys, restricting them would not restrict
+    // the keys they create
+    if payload.scopes.contains(&Scope::Admin) && !payload.guild_ids.is_empty() {
+        errors.push(FieldError::new(
+            "guild_ids",
+            "admin keys cannot be restricted to guilds".to_string(),
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PollError::Validation(errors))
+    }
+}
+
+// Turns the query into a filter, an empty value is the same as no value.
+pub fn validate_polls_query(query: PollsQuery) -> Result<PollFilter, PollError> {
+    let mut errors: Vec<FieldError> = Vec::new();
+    let mut filter = PollFilter::default();
+
+    if let Some(sort) = query.sort.filter(|sort| !sort.is_empty()) {
+        match sort.parse::<PollSort>() {
+            Ok(sort) => filter.sort = sort,
+            Err(e) => errors.push(FieldError::new("sort", e)),
+        }
+    }
+
+    if let Some(cursor) = query.cursor.filter(|cursor| !cursor.is_empty()) {
+        match PollCursor::decode(&cursor) {
+            Ok(cursor) if cursor.sort != filter.sort => errors.push(FieldError::new(
+                "cursor",
+                format!("was made for the {} sort", cursor.sort),
+            )),
+            Ok(cursor) => filter.after = Some(cursor),
+            Err(e) => errors.push(FieldError::new("cursor", e)),
+        }
+    }
+
+    if let Some(limit) = query.limit {
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            errors.push(FieldError::new(
+                "limit",
+                format!("must be between 1 and {}", MAX_PAGE_SIZE),
+            ));
+        }
+        filter.limit = limit;
+    }
+
+    filter.guild = query.guild.filter(|guild| !guild.is_empty());
+    filter.channel = query.channel.filter(|channel| !channel.is_empty());
+    filter.onetime = query.onetime;
+    filter.sent = query.sent;
+    filter.question = query.q.filter(|q| !q.trim().is_empty());
+
+    if errors.is_empty() {
+        Ok(filter)
+    } else {
+        Err(PollError::Validation(errors))
+    }
+}
+
+fn validate_schedule(cron: &str, timezone: &str, errors: &mut Vec<FieldError>) {
+    if let Err(e) = parse_cron(cron) {
+        errors.push(FieldError::new(
+            "cron",
+            format!("invalid cron expression: {}", e),
+        ));
+    }
+
+    if timezone.parse::<chrono_tz::Tz>().is_err() {
+        errors.push(FieldError::new(
+            "timezone",
+            format!("unknown timezone: {}", timezone),
+        ));
+    }
+}
+
+fn validate_schedule_count(count: Option<usize>, errors: &mut Vec<FieldError>) -> usize {
+    let count = count.unwrap_or(DEFAULT_SCHEDULE_COUNT);
+
+    if count == 0 || count > MAX_SCHEDULE_COUNT {
+        errors.push(FieldError::new(
+            "count",
+            format!("must be between 1 and {}", MAX_SCHEDULE_COUNT),
+        ));
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::domain::{MisfirePolicy, Poll};
+    use crate::poll::search::DEFAULT_PAGE_SIZE;
+
+    fn valid_payload() -> CreatePoll {
+        CreatePoll {
+            cron: String::from("0 9 * * 1-5"),
+            question: String::from("a question"),
+            answers: vec![String::from("one"), String::from("two")],
+            multiselect: false,
+            guild: String::from("guild"),
+            channel: String::from("channel"),
+            guild_id: None,
+            channel_id: None,
+            duration: 3600,
+            onetime: false,
+            timezone: String::from("Europe/Paris"),
+            misfire_policy: MisfirePolicy::FireOnce,
+            start_at: None,
+            end_at: None,
+            max_occurrences: None,
+            expose_voters: false,
+            announce_results: false,
+            results_template: None,
+            results_channel_id: None,
+        }
+    }
+
+    fn fields(error: PollError) -> Vec<String> {
+        match error {
+            PollError::Validation(errors) => errors.into_iter().map(|e| e.field).collect(),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_valid_poll() {
+        assert!(validate_poll(&valid_payload()).is_ok());
+    }
+
+    #[test]
+    fn test_valid_poll_with_seconds() {
+        let mut payload = valid_payload();
+        payload.cron = String::from("30 0 9 * * *");
+        assert!(validate_poll(&payload).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_cron() {
+        let mut payload = valid_payload();
+        payload.cron = String::from("every monday");
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["cron"], fields(errors));
+    }
+
+    #[test]
+    fn test_invalid_timezone() {
+        let mut payload = valid_payload();
+        payload.timezone = String::from("Europe/Atlantis");
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["timezone"], fields(errors));
+    }
+
+    #[test]
+    fn test_empty_question() {
+        let mut payload = valid_payload();
+        payload.question = String::from("   ");
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["question"], fields(errors));
+    }
+
+    #[test]
+    fn test_question_too_long() {
+        let mut payload = valid_payload();
+        payload.question = "?".repeat(MAX_QUESTION_LENGTH + 1);
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["question"], fields(errors));
+    }
+
+    #[test]
+    fn test_no_answers() {
+        let mut payload = valid_payload();
+        payload.answers = vec![];
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["answers"], fields(errors));
+    }
+
+    #[test]
+    fn test_too_many_answers() {
+        let mut payload = valid_payload();
+        payload.answers = (0..=MAX_ANSWERS).map(|i| i.to_string()).collect();
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["answers"], fields(errors));
+    }
+
+    #[test]
+    fn test_invalid_answers() {
+        let mut payload = valid_payload();
+        payload.answers = vec![
+            String::from("one"),
+            String::new(),
+            "!".repeat(MAX_ANSWER_LENGTH + 1),
+            String::from("one"),
+        ];
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(
+            vec!["answers[1]", "answers[2]", "answers[3]"],
+            fields(errors)
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        let mut payload = valid_payload();
+        payload.guild = String::new();
+        payload.channel = String::new();
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["channel"], fields(errors));
+
+        payload.channel_id = Some(1234);
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["guild_id"], fields(errors));
+
+        payload.guild_id = Some(5678);
+        assert!(validate_poll(&payload).is_ok());
+    }
+
+    #[test]
+    fn test_ids_not_positive() {
+        let mut payload = valid_payload();
+        payload.guild_id = Some(0);
+        payload.channel_id = Some(-1);
+        payload.results_channel_id = Some(0);
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(
+            vec!["guild_id", "channel_id", "results_channel_id"],
+            fields(errors)
+        );
+    }
+
+    #[test]
+    fn test_channel_name() {
+        let channel = |id: i64| Channel {
+            id,
+            guild_id: 1234,
+            name: String::from("channel"),
+            kind: String::from("text"),
+            parent_id: None,
+        };
+
+        assert_eq!(
+            5678,
+            validate_channel_name("guild", "channel", vec![channel(5678)])
+                .unwrap()
+                .id
+        );
+        assert_eq!(
+            vec!["channel"],
+            fields(validate_channel_name("guild", "channel", vec![]).unwrap_err())
+        );
+        assert_eq!(
+            vec!["channel"],
+            fields(
+                validate_channel_name("guild", "channel", vec![channel(5678), channel(5679)])
+                    .unwrap_err()
+            )
+        );
+    }
+
+    #[test]
+    fn test_channels() {
+        let channels = vec![
+            Channel {
+                id: 5678,
+                guild_id: 1234,
+                name: String::from("channel"),
+                kind: String::from("text"),
+                parent_id: None,
+            },
+            Channel {
+                id: 8765,
+                guild_id: 4321,
+                name: String::from("channel"),
+                kind: String::from("text"),
+                parent_id: None,
+            },
+        ];
+
+        assert_eq!(
+            true,
+            validate_channels(
+                1234,
+                &[("channel_id", Some(5678)), ("results_channel_id", None)],
+                &channels
+            )
+            .is_ok()
+        );
+
+        // channels of another guild, and unknown channels
+        assert_eq!(
+            vec!["channel_id", "results_channel_id"],
+            fields(
+                validate_channels(
+                    1234,
+                    &[
+                        ("channel_id", Some(8765)),
+                        ("results_channel_id", Some(9999))
+                    ],
+                    &channels
+                )
+                .unwrap_err()
+            )
+        );
+    }
+
+    #[test]
+    fn test_renames() {
+        let rename = |from: &str, to: &str| AnswerRename {
+            from: String::from(from),
+            to: String::from(to),
+        };
+        let saved = vec![String::from("pizza"), String::from("sushi")];
+        let answers = vec![String::from("pizza"), String::from("ramen")];
+
+        assert_eq!(true, validate_renames(&[], &saved, &answers).is_ok());
+        assert_eq!(
+            true,
+            validate_renames(&[rename("sushi", "ramen")], &saved, &answers).is_ok()
+        );
+
+        // unknown, kept and renamed twice
+        assert_eq!(
+            vec!["renames"],
+            fields(validate_renames(&[rename("tacos", "ramen")], &saved, &answers).unwrap_err())
+        );
+        assert_eq!(
+            vec!["renames"],
+            fields(validate_renames(&[rename("pizza", "ramen")], &saved, &answers).unwrap_err())
+        );
+        assert_eq!(
+            vec!["renames"],
+            fields(
+                validate_renames(
+                    &[rename("sushi", "ramen"), rename("sushi", "ramen")],
+                    &saved,
+                    &answers
+                )
+                .unwrap_err()
+            )
+        );
+    }
+
+    #[test]
+    fn test_duration_out_of_range() {
+        let mut payload = valid_payload();
+        payload.duration = 100;
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["duration"], fields(errors));
+
+        payload.duration = MAX_DURATION + 1;
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["duration"], fields(errors));
+
+        // discord polls last 7 days at most
+        payload.duration = 8 * 24 * 3600;
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["duration"], fields(errors));
+
+        payload.duration = 7 * 24 * 3600;
+        assert!(validate_poll(&payload).is_ok());
+    }
+
+    #[test]
+    fn test_duration_not_whole_hours() {
+        let mut payload = valid_payload();
+        payload.duration = 5400;
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["duration"], fields(errors));
+
+        payload.duration = 2 * 3600;
+        assert!(validate_poll(&payload).is_ok());
+    }
+
+    #[test]
+    fn test_validity_window() {
+        let now = Utc::now();
+        let mut payload = valid_payload();
+        payload.start_at = Some(now);
+        payload.end_at = Some(now + chrono::Duration::days(7));
+        payload.max_occurrences = Some(1);
+        assert!(validate_poll(&payload).is_ok());
+
+        payload.end_at = Some(now);
+        payload.max_occurrences = Some(0);
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["end_at", "max_occurrences"], fields(errors));
+    }
+
+    #[test]
+    fn test_results_template() {
+        let mut payload = valid_payload();
+        payload.announce_results = true;
+        payload.results_template = Some(String::from("{question}: {winner}"));
+        assert!(validate_poll(&payload).is_ok());
+
+        payload.results_template = Some(String::from("{winner} with {votes}"));
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["results_template"], fields(errors));
+
+        payload.results_template = Some(String::from(" "));
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(vec!["results_template"], fields(errors));
+    }
+
+    #[test]
+    fn test_schedule_preview() {
+        let mut payload = SchedulePreview {
+            cron: String::from("0 9 * * 1-5"),
+            timezone: String::from("America/New_York"),
+            count: None,
+        };
+        assert_eq!(
+            DEFAULT_SCHEDULE_COUNT,
+            validate_schedule_preview(&payload).unwrap()
+        );
+
+        payload.count = Some(MAX_SCHEDULE_COUNT + 1);
+        payload.cron = String::from("0 25 * * *");
+        let errors = validate_schedule_preview(&payload).unwrap_err();
+        assert_eq!(vec!["cron", "count"], fields(errors));
+    }
+
+    #[test]
+    fn test_pause() {
+        let now = Utc::now();
+        assert!(validate_pause(None, &now).is_ok());
+        assert!(validate_pause(Some(now + chrono::Duration::hours(1)), &now).is_ok());
+
+        let errors = validate_pause(Some(now), &now).unwrap_err();
+        assert_eq!(vec!["until"], fields(errors));
+    }
+
+    #[test]
+    fn test_stats_query() {
+        let now = Utc::now();
+
+        assert!(validate_stats_query(None, None).is_ok());
+        assert!(validate_stats_query(Some(now), None).is_ok());
+        assert!(validate_stats_query(None, Some(now)).is_ok());
+        assert!(validate_stats_query(Some(now), Some(now + chrono::Duration::days(1))).is_ok());
+        assert_eq!(
+            vec!["to"],
+            fields(validate_stats_query(Some(now), Some(now)).unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_every_error_reported() {
+        let payload = CreatePoll {
+            cron: String::new(),
+            question: String::new(),
+            answers: vec![],
+            multiselect: false,
+            guild: String::new(),
+            channel: String::new(),
+            guild_id: None,
+            channel_id: None,
+            duration: 0,
+            onetime: false,
+            timezone: String::new(),
+            misfire_policy: MisfirePolicy::FireOnce,
+            start_at: None,
+            end_at: None,
+            max_occurrences: None,
+            expose_voters: false,
+            announce_results: false,
+            results_template: None,
+            results_channel_id: None,
+        };
+        let errors = validate_poll(&payload).unwrap_err();
+        assert_eq!(
+            vec!["cron", "timezone", "question", "answers", "channel", "duration"],
+            fields(errors)
+        );
+    }
+
+    #[test]
+    fn test_api_key() {
+        let payload = CreateApiKey {
+            name: String::from("dashboard"),
+            scopes: vec![Scope::PollsRead, Scope::ResultsRead],
+            guild_ids: vec![1234],
+        };
+        assert!(validate_api_key(&payload).is_ok());
+
+        let payload = CreateApiKey {
+            name: String::from(" "),
+            scopes: vec![],
+            guild_ids: vec![],
+        };
+        assert_eq!(
+            vec!["name", "scopes"],
+            fields(validate_api_key(&payload).unwrap_err())
+        );
+
+        let payload = CreateApiKey {
+            name: "a".repeat(MAX_API_KEY_NAME_LENGTH + 1),
+            scopes: vec![Scope::Admin],
+            guild_ids: vec![1234],
+        };
+        assert_eq!(
+            vec!["name", "guild_ids"],
+            fields(validate_api_key(&payload).unwrap_err())
+        );
+    }
+
+    fn polls_query() -> PollsQuery {
+        PollsQuery {
+            guild: None,
+            channel: None,
+            onetime: None,
+            sent: None,
+            q: None,
+            sort: None,
+            cursor: None,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_polls_query() {
+        let filter = validate_polls_query(polls_query()).unwrap();
+        assert_eq!(PollSort::CreatedAt, filter.sort);
+        assert_eq!(DEFAULT_PAGE_SIZE, filter.limit);
+        assert!(filter.after.is_none());
+
+        let cursor = PollCursor::after(PollSort::QuestionDesc, &Poll::new());
+        let filter = validate_polls_query(PollsQuery {
+            guild: Some(String::from("1234")),
+            channel: Some(String::new()),
+            q: Some(String::from("lunch")),
+            sort: Some(String::from("-question")),
+            cursor: Some(cursor.encode()),
+            limit: Some(10),
+            ..polls_query()
+        })
+        .unwrap();
+        assert_eq!(Some(String::from("1234")), filter.guild);
+        assert_eq!(None, filter.channel);
+        assert_eq!(Some(String::from("lunch")), filter.question);
+        assert_eq!(PollSort::QuestionDesc, filter.sort);
+        assert_eq!(Some(cursor), filter.after);
+        assert_eq!(10, filter.limit);
+    }
+
+    #[test]
+    fn test_invalid_polls_query() {
+        let query = PollsQuery {
+            sort: Some(String::from("id")),
+            cursor: Some(String::from("abc")),
+            limit: Some(MAX_PAGE_SIZE + 1),
+            ..polls_query()
+        };
+        assert_eq!(
+            vec!["sort", "cursor", "limit"],
+            fields(validate_polls_query(query).unwrap_err())
+        );
+
+        // cursors only continue the sort they were made for
+        let cursor = PollCursor::after(PollSort::Question, &Poll::new());
+        let query = PollsQuery {
+            cursor: Some(cursor.encode()),
+            limit: Some(0),
+            ..polls_query()
+        };
+        assert_eq!(
+            vec!["cursor", "limit"],
+            fields(validate_polls_query(query).unwrap_err())
+        );
+    }
+}
@@ -0,0 +1,5 @@
+pub mod api_key_use_cases;
+pub mod domain;
+pub mod oauth;
+mod repository;
+pub mod session_use_cases;
@@ -0,0 +1,90 @@
+use crate::auth::domain::{generate_key, hash_key, ApiKey, Scope, MIN_KEY_LENGTH};
+use crate::auth::repository::ApiKeyRepository;
+use crate::poll::error::{FieldError, PollError};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const BOOTSTRAP_KEY_NAME: &str = "bootstrap";
+
+pub struct ApiKeyUseCases<'a> {
+    api_key_repository: ApiKeyRepository<'a>,
+}
+
+impl ApiKeyUseCases<'_> {
+    pub fn new(pool: &'_ PgPool) -> ApiKeyUseCases<'_> {
+        ApiKeyUseCases {
+            api_key_repository: ApiKeyRepository { pool },
+        }
+    }
+
+    // Finds the active key, revoked and unknown keys are unauthorized alike.
+    pub async fn authenticate(&self, key: &str, at: DateTime<Utc>) -> Result<ApiKey, PollError> {
+        self.api_key_repository
+            .find_active_by_hash(&hash_key(key), at)
+            .await?
+            .ok_or_else(|| PollError::Unauthorized(String::from("invalid api key")))
+    }
+
+    // Returns the key created along with its secret, which cannot be
+    // retrieved later on.
+    pub async fn create_api_key(
+        &self,
+        name: String,
+        scopes: Vec<Scope>,
+        guild_ids: Vec<i64>,
+    ) -> Result<(ApiKey, String), PollError> {
+        let key = generate_key();
+        let api_key = ApiKey::new(name, &key, scopes, guild_ids);
+        self.api_key_repository
+            .create(&api_key, &hash_key(&key))
+            .await?;
+
+        Ok((api_key, key))
+    }
+
+    pub async fn get_api_keys(&self) -> Result<Vec<ApiKey>, PollError> {
+        self.api_key_repository.get_all().await
+    }
+
+    pub async fn revoke_api_key(&self, id: Uuid, at: DateTime<Utc>) -> Result<(), PollError> {
+        if !self.api_key_repository.revoke(id, at).await? {
+            return Err(PollError::not_found("api key", id));
+        }
+
+        Ok(())
+    }
+
+    // Creates an admin key when there is none so that the other keys can be
+    // created. The key provided is used, otherwise one is generated. Returns
+    // the key created if any.
+    pub async fn bootstrap_admin_key(
+        &self,
+        key: Option<String>,
+    ) -> Result<Option<String>, PollError> {
+        if key
+            .as_ref()
+            .is_some_and(|key| key.chars().count() < MIN_KEY_LENGTH)
+        {
+            return Err(PollError::Validation(vec![FieldError::new(
+                "key",
+                format!("must be at least {} characters", MIN_KEY_LENGTH),
+            )]));
+        }
+
+        let key = key.unwrap_or_else(generate_key);
+        let api_key = ApiKey::new(
+            String::from(BOOTSTRAP_KEY_NAME),
+            &key,
+            vec![Scope::Admin],
+            vec![],
+        );
+
+        let created = self
+            .api_key_repository
+            .create_first_admin(&api_key, &hash_key(&key))
+            .await?;
+
+        Ok(created.then_some(key))
+    }
+}
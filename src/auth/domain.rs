@@ -0,0 +1,270 @@
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// keys are told apart by their prefix, e.g. in the list of keys
+pub const KEY_PREFIX: &str = "cpd_";
+pub const KEY_PREFIX_LENGTH: usize = 12;
+// random bytes of a generated key
+const KEY_BYTES: usize = 32;
+// keys provided rather than generated, e.g. the bootstrap one, must be at
+// least that long
+pub const MIN_KEY_LENGTH: usize = 32;
+
+// What an api key is allowed to do, admin keys are allowed to do everything
+// including managing the keys.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq)]
+pub enum Scope {
+    #[serde(rename = "polls:read")]
+    PollsRead,
+    #[serde(rename = "polls:write")]
+    PollsWrite,
+    #[serde(rename = "results:read")]
+    ResultsRead,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PollsRead => write!(f, "polls:read"),
+            Self::PollsWrite => write!(f, "polls:write"),
+            Self::ResultsRead => write!(f, "results:read"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "polls:read" => Ok(Self::PollsRead),
+            "polls:write" => Ok(Self::PollsWrite),
+            "results:read" => Ok(Self::ResultsRead),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("unknown scope: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    // empty when the key is allowed on every guild
+    pub guild_ids: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn new(name: String, key: &str, scopes: Vec<Scope>, guild_ids: Vec<i64>) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            name,
+            prefix: key_prefix(key),
+            scopes,
+            guild_ids,
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| *s == scope || *s == Scope::Admin)
+    }
+
+    // Polls created from guild names have no guild id until they are sent,
+    // only keys allowed on every guild can access them.
+    pub fn allows_guild(&self, guild_id: Option<i64>) -> bool {
+        self.guild_ids.is_empty() || guild_id.is_some_and(|id| self.guild_ids.contains(&id))
+    }
+}
+
+// Session of a user logged in with discord.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    // guilds the user manages, known at login
+    pub guild_ids: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn is_expired(&self, at: &DateTime<Utc>) -> bool {
+        self.expires_at <= *at
+    }
+}
+
+// Who a request is made by, either an api key or a user logged in with
+// discord.
+#[derive(Debug, Clone)]
+pub enum Caller {
+    ApiKey(ApiKey),
+    User(Session),
+}
+
+impl Caller {
+    // Users manage the polls of their guilds, not the api keys.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match self {
+            Self::ApiKey(key) => key.has_scope(scope),
+            Self::User(_) => scope != Scope::Admin,
+        }
+    }
+
+    pub fn allows_guild(&self, guild_id: Option<i64>) -> bool {
+        match self {
+            Self::ApiKey(key) => key.allows_guild(guild_id),
+            Self::User(session) => guild_id.is_some_and(|id| session.guild_ids.contains(&id)),
+        }
+    }
+
+    // Guilds the caller is restricted to, none when allowed on every guild.
+    pub fn guild_ids(&self) -> Option<&[i64]> {
+        match self {
+            Self::ApiKey(key) if key.guild_ids.is_empty() => None,
+            Self::ApiKey(key) => Some(&key.guild_ids),
+            Self::User(session) => Some(&session.guild_ids),
+        }
+    }
+}
+
+// Generates a random key, it is only shown once as only its hash is stored.
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; KEY_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    format!("{}{}", KEY_PREFIX, to_hex(&bytes))
+}
+
+// Keys are random rather than chosen by users, a fast hash is enough.
+pub fn hash_key(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+pub fn key_prefix(key: &str) -> String {
+    key.chars().take(KEY_PREFIX_LENGTH).collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_key(scopes: Vec<Scope>, guild_ids: Vec<i64>) -> ApiKey {
+        ApiKey::new(String::from("test"), &generate_key(), scopes, guild_ids)
+    }
+
+    #[test]
+    fn test_has_scope() {
+        let key = create_key(vec![Scope::PollsRead, Scope::ResultsRead], vec![]);
+        assert!(key.has_scope(Scope::PollsRead));
+        assert!(key.has_scope(Scope::ResultsRead));
+        assert!(!key.has_scope(Scope::PollsWrite));
+        assert!(!key.has_scope(Scope::Admin));
+
+        let admin = create_key(vec![Scope::Admin], vec![]);
+        assert!(admin.has_scope(Scope::PollsWrite));
+        assert!(admin.has_scope(Scope::Admin));
+    }
+
+    #[test]
+    fn test_allows_guild() {
+        let key = create_key(vec![Scope::PollsRead], vec![]);
+        assert!(key.allows_guild(Some(1)));
+        assert!(key.allows_guild(None));
+
+        let key = create_key(vec![Scope::PollsRead], vec![1, 2]);
+        assert!(key.allows_guild(Some(2)));
+        assert!(!key.allows_guild(Some(3)));
+        assert!(!key.allows_guild(None));
+    }
+
+    #[test]
+    fn test_caller() {
+        let now = Utc::now();
+        let user = Caller::User(Session {
+            user_id: 100,
+            username: String::from("user"),
+            guild_ids: vec![1],
+            created_at: now,
+            expires_at: now,
+        });
+        assert!(user.has_scope(Scope::PollsWrite));
+        assert!(!user.has_scope(Scope::Admin));
+        assert!(user.allows_guild(Some(1)));
+        assert!(!user.allows_guild(Some(2)));
+        assert!(!user.allows_guild(None));
+        assert_eq!(Some(&[1][..]), user.guild_ids());
+
+        // users without guilds are not allowed anywhere
+        let user = Caller::User(Session {
+            user_id: 100,
+            username: String::from("user"),
+            guild_ids: vec![],
+            created_at: now,
+            expires_at: now,
+        });
+        assert!(!user.allows_guild(Some(1)));
+        assert_eq!(Some(&[][..]), user.guild_ids());
+
+        let key = Caller::ApiKey(create_key(vec![Scope::PollsRead], vec![]));
+        assert!(key.allows_guild(Some(2)));
+        assert!(!key.has_scope(Scope::PollsWrite));
+        assert_eq!(None, key.guild_ids());
+
+        let key = Caller::ApiKey(create_key(vec![Scope::PollsRead], vec![2]));
+        assert_eq!(Some(&[2][..]), key.guild_ids());
+    }
+
+    #[test]
+    fn test_scope() {
+        for scope in [
+            Scope::PollsRead,
+            Scope::PollsWrite,
+            Scope::ResultsRead,
+            Scope::Admin,
+        ] {
+            assert_eq!(Ok(scope), scope.to_string().parse::<Scope>());
+        }
+        assert!("polls:delete".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let key = generate_key();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(KEY_PREFIX.len() + KEY_BYTES * 2, key.len());
+        assert_ne!(key, generate_key());
+
+        let hash = hash_key(&key);
+        assert_eq!(64, hash.len());
+        assert_eq!(hash, hash_key(&key));
+        assert_eq!(
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            hash_key("hello")
+        );
+
+        assert_eq!(&key[..KEY_PREFIX_LENGTH], key_prefix(&key));
+    }
+}
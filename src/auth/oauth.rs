@@ -0,0 +1,392 @@
+use crate::poll::error::PollError;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serenity::http::HttpError;
+
+pub const DISCORD_AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+pub const DISCORD_TOKEN_URL: &str = "https://discord.com/api/oauth2/token";
+pub const DISCORD_API_URL: &str = "https://discord.com/api/v10";
+
+// permissions granting the management of a guild
+const ADMINISTRATOR: u64 = 1 << 3;
+const MANAGE_GUILD: u64 = 1 << 5;
+
+// The endpoints default to discord ones, they are configurable so that
+// another provider can stand in for discord.
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    // base of the user info endpoints
+    pub api_url: String,
+    // members with one of these roles manage the guild as well
+    pub role_ids: Vec<i64>,
+}
+
+impl OAuthConfig {
+    pub fn new(client_id: String, client_secret: String, redirect_uri: String) -> OAuthConfig {
+        OAuthConfig {
+            client_id,
+            client_secret,
+            redirect_uri,
+            authorize_url: String::from(DISCORD_AUTHORIZE_URL),
+            token_url: String::from(DISCORD_TOKEN_URL),
+            api_url: String::from(DISCORD_API_URL),
+            role_ids: vec![],
+        }
+    }
+
+    // Cookies of an api served over https are only sent over https.
+    pub fn secure_cookies(&self) -> bool {
+        self.redirect_uri.starts_with("https://")
+    }
+
+    fn scopes(&self) -> &'static str {
+        // the roles of the user are only readable with guilds.members.read
+        if self.role_ids.is_empty() {
+            "identify guilds"
+        } else {
+            "identify guilds guilds.members.read"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthUser {
+    pub id: i64,
+    pub username: String,
+    // guilds the user manages
+    pub guild_ids: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UserResponse {
+    id: String,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct GuildResponse {
+    id: String,
+    #[serde(default)]
+    owner: bool,
+    #[serde(default)]
+    permissions: String,
+}
+
+#[derive(Deserialize)]
+struct MemberResponse {
+    roles: Vec<String>,
+}
+
+pub struct OAuthClient {
+    pub config: OAuthConfig,
+    http: reqwest::Client,
+}
+
+impl OAuthClient {
+    pub fn new(config: OAuthConfig) -> Result<OAuthClient, String> {
+        for url in [&config.authorize_url, &config.token_url, &config.api_url] {
+            Url::parse(url).map_err(|e| format!("invalid url {}: {}", url, e))?;
+        }
+
+        Ok(OAuthClient {
+            config,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    // Where users are sent to authorize the api, the state is sent back to
+    // the redirect uri along with the code.
+    pub fn authorize_url(&self, state: &str) -> String {
+        Url::parse_with_params(
+            &self.config.authorize_url,
+            &[
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("response_type", "code"),
+                ("scope", self.config.scopes()),
+                ("state", state),
+            ],
+        )
+        .map(String::from)
+        .unwrap_or_else(|_| self.config.authorize_url.clone())
+    }
+
+    // Exchanges the code for the user and the guilds they manage. Roles are
+    // only looked up in the guilds given, e.g. the ones of the bot, as each
+    // guild is a request.
+    pub async fn login(&self, code: &str, role_guild_ids: &[i64]) -> Result<OAuthUser, PollError> {
+        let token = self.exchange_code(code).await?;
+
+        let user: UserResponse = self.get(&token, "/users/@me").await?;
+        let guilds: Vec<GuildResponse> = self.get(&token, "/users/@me/guilds").await?;
+
+        let mut guild_ids: Vec<i64> = Vec::new();
+        for guild in guilds {
+            let guild_id = parse_id(&guild.id)?;
+
+            if guild.owner || manages_guild(&guild.permissions) {
+                guild_ids.push(guild_id);
+            } else if !self.config.role_ids.is_empty() && role_guild_ids.contains(&guild_id) {
+                let member: MemberResponse = self
+                    .get(&token, &format!("/users/@me/guilds/{}/member", guild_id))
+                    .await?;
+                if member
+                    .roles
+                    .iter()
+                    .any(|role| parse_id(role).is_ok_and(|id| self.config.role_ids.contains(&id)))
+                {
+                    guild_ids.push(guild_id);
+                }
+            }
+        }
+
+        Ok(OAuthUser {
+            id: parse_id(&user.id)?,
+            username: user.username,
+            guild_ids,
+        })
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<String, PollError> {
+        let response = self
+            .http
+            .post(&self.config.token_url)
+            .form(&[
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        // expired or already used codes
+        if response.status() == StatusCode::BAD_REQUEST {
+            return Err(PollError::Unauthorized(String::from(
+                "invalid authorization code",
+            )));
+        }
+
+        let token: TokenResponse = response
+            .error_for_status()
+            .map_err(request_error)?
+            .json()
+            .await
+            .map_err(request_error)?;
+
+        Ok(token.access_token)
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(
+        &self,
+        token: &str,
+        path: &str,
+    ) -> Result<T, PollError> {
+        self.http
+            .get(format!("{}{}", self.config.api_url, path))
+            .bearer_auth(token)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(request_error)?
+            .json()
+            .await
+            .map_err(request_error)
+    }
+}
+
+fn manages_guild(permissions: &str) -> bool {
+    permissions
+        .parse::<u64>()
+        .is_ok_and(|p| p & (ADMINISTRATOR | MANAGE_GUILD) != 0)
+}
+
+fn parse_id(id: &str) -> Result<i64, PollError> {
+    id.parse::<i64>().map_err(PollError::decode)
+}
+
+// Failures of the provider are reported like the ones of the bot.
+fn request_error(e: reqwest::Error) -> PollError {
+    PollError::Discord(Box::new(serenity::Error::Http(HttpError::Request(e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Form, Path};
+    use axum::http::{HeaderMap, StatusCode as AxumStatusCode};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    const ACCESS_TOKEN: &str = "access-token";
+
+    #[derive(Serialize)]
+    struct Guild {
+        id: String,
+        owner: bool,
+        permissions: String,
+    }
+
+    fn guild(id: &str, owner: bool, permissions: u64) -> Guild {
+        Guild {
+            id: String::from(id),
+            owner,
+            permissions: permissions.to_string(),
+        }
+    }
+
+    fn authorized(headers: &HeaderMap) -> bool {
+        headers
+            .get("authorization")
+            .is_some_and(|v| v == &format!("Bearer {}", ACCESS_TOKEN))
+    }
+
+    // Stands in for discord, the user owns guild 1, manages guild 2 and 3,
+    // is a member of guild 4 with role 40 and of guild 5 without roles.
+    async fn stand_in() -> String {
+        let app = Router::new()
+            .route(
+                "/token",
+                post(|Form(form): Form<HashMap<String, String>>| async move {
+                    if form.get("code").map(String::as_str) != Some("code")
+                        || form.get("grant_type").map(String::as_str) != Some("authorization_code")
+                    {
+                        return Err(AxumStatusCode::BAD_REQUEST);
+                    }
+                    Ok(Json(HashMap::from([("access_token", ACCESS_TOKEN)])))
+                }),
+            )
+            .route(
+                "/api/users/@me",
+                get(|headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        return Err(AxumStatusCode::UNAUTHORIZED);
+                    }
+                    Ok(Json(HashMap::from([("id", "100"), ("username", "user")])))
+                }),
+            )
+            .route(
+                "/api/users/@me/guilds",
+                get(|headers: HeaderMap| async move {
+                    if !authorized(&headers) {
+                        return Err(AxumStatusCode::UNAUTHORIZED);
+                    }
+                    Ok(Json(vec![
+                        guild("1", true, 0),
+                        guild("2", false, MANAGE_GUILD),
+                        guild("3", false, ADMINISTRATOR | 1),
+                        guild("4", false, 1),
+                        guild("5", false, 1),
+                    ]))
+                }),
+            )
+            .route(
+                "/api/users/@me/guilds/{id}/member",
+                get(|Path(id): Path<String>| async move {
+                    let roles: Vec<&str> = if id == "4" { vec!["40"] } else { vec![] };
+                    Json(HashMap::from([("roles", roles)]))
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{}", address)
+    }
+
+    async fn create_client(role_ids: Vec<i64>) -> OAuthClient {
+        let url = stand_in().await;
+        let mut config = OAuthConfig::new(
+            String::from("client"),
+            String::from("secret"),
+            String::from("http://localhost:3000/auth/callback"),
+        );
+        config.token_url = format!("{}/token", url);
+        config.api_url = format!("{}/api", url);
+        config.role_ids = role_ids;
+
+        OAuthClient::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let client = create_client(vec![]).await;
+
+        let user = client.login("code", &[4, 5]).await.unwrap();
+        assert_eq!(
+            OAuthUser {
+                id: 100,
+                username: String::from("user"),
+                guild_ids: vec![1, 2, 3],
+            },
+            user
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_with_roles() {
+        let client = create_client(vec![40]).await;
+
+        let user = client.login("code", &[4, 5]).await.unwrap();
+        assert_eq!(vec![1, 2, 3, 4], user.guild_ids);
+
+        // roles are not looked up outside of the guilds given
+        let user = client.login("code", &[5]).await.unwrap();
+        assert_eq!(vec![1, 2, 3], user.guild_ids);
+    }
+
+    #[tokio::test]
+    async fn test_login_invalid_code() {
+        let client = create_client(vec![]).await;
+
+        assert!(matches!(
+            client.login("other", &[]).await,
+            Err(PollError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let mut config = OAuthConfig::new(
+            String::from("client"),
+            String::from("secret"),
+            String::from("https://polls.example.com/auth/callback"),
+        );
+        let client = OAuthClient::new(config.clone()).unwrap();
+        assert_eq!(
+            "https://discord.com/oauth2/authorize?client_id=client&redirect_uri=https%3A%2F%2Fpolls.example.com%2Fauth%2Fcallback&response_type=code&scope=identify+guilds&state=abc",
+            client.authorize_url("abc")
+        );
+        assert!(client.config.secure_cookies());
+
+        config.role_ids = vec![40];
+        let client = OAuthClient::new(config).unwrap();
+        assert!(client
+            .authorize_url("abc")
+            .contains("scope=identify+guilds+guilds.members.read"));
+
+        let mut config = OAuthConfig::new(
+            String::from("client"),
+            String::from("secret"),
+            String::from("http://localhost:3000/auth/callback"),
+        );
+        assert!(!config.secure_cookies());
+        config.token_url = String::from("not a url");
+        assert!(OAuthClient::new(config).is_err());
+    }
+}
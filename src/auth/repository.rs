@@ -0,0 +1,188 @@
+use crate::auth::domain::{ApiKey, Scope, Session};
+use crate::poll::error::PollError;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::Row;
+use uuid::Uuid;
+
+pub struct ApiKeyRepository<'a> {
+    pub pool: &'a PgPool,
+}
+
+pub struct SessionRepository<'a> {
+    pub pool: &'a PgPool,
+}
+
+fn api_key_from_row(row: &PgRow) -> Result<ApiKey, PollError> {
+    let id: String = row.try_get("id")?;
+    let scopes: Vec<String> = row.try_get("scopes")?;
+
+    Ok(ApiKey {
+        id: Uuid::parse_str(&id).map_err(PollError::decode)?,
+        name: row.try_get("name")?,
+        prefix: row.try_get("prefix")?,
+        scopes: scopes
+            .iter()
+            .map(|s| s.parse::<Scope>())
+            .collect::<Result<Vec<Scope>, String>>()
+            .map_err(PollError::decode)?,
+        guild_ids: row.try_get("guild_ids")?,
+        created_at: row.try_get("created_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+        revoked_at: row.try_get("revoked_at")?,
+    })
+}
+
+fn session_from_row(row: &PgRow) -> Result<Session, PollError> {
+    Ok(Session {
+        user_id: row.try_get("user_id")?,
+        username: row.try_get("username")?,
+        guild_ids: row.try_get("guild_ids")?,
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+    })
+}
+
+fn scopes(key: &ApiKey) -> Vec<String> {
+    key.scopes.iter().map(|s| s.to_string()).collect()
+}
+
+impl ApiKeyRepository<'_> {
+    pub async fn create(&self, key: &ApiKey, key_hash: &str) -> Result<(), PollError> {
+        sqlx::query(
+            "
+INSERT INTO api_keys (id, name, prefix, key_hash, scopes, guild_ids, created_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(key.id.to_string())
+        .bind(key.name.clone())
+        .bind(key.prefix.clone())
+        .bind(key_hash)
+        .bind(scopes(key))
+        .bind(key.guild_ids.clone())
+        .bind(key.created_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Creates the key unless an admin key is already active, returns whether
+    // it was created.
+    pub async fn create_first_admin(
+        &self,
+        key: &ApiKey,
+        key_hash: &str,
+    ) -> Result<bool, PollError> {
+        let result = sqlx::query(
+            "
+INSERT INTO api_keys (id, name, prefix, key_hash, scopes, guild_ids, created_at)
+SELECT $1, $2, $3, $4, $5, $6, $7
+WHERE NOT EXISTS (
+    SELECT 1 FROM api_keys WHERE $8 = ANY(scopes) AND revoked_at IS NULL
+)
+ON CONFLICT (key_hash) DO NOTHING",
+        )
+        .bind(key.id.to_string())
+        .bind(key.name.clone())
+        .bind(key.prefix.clone())
+        .bind(key_hash)
+        .bind(scopes(key))
+        .bind(key.guild_ids.clone())
+        .bind(key.created_at)
+        .bind(Scope::Admin.to_string())
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    // Finds the active key with that hash and records its use.
+    pub async fn find_active_by_hash(
+        &self,
+        key_hash: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Option<ApiKey>, PollError> {
+        let row = sqlx::query(
+            "
+UPDATE api_keys
+SET last_used_at = $1
+WHERE key_hash = $2 AND revoked_at IS NULL
+RETURNING *",
+        )
+        .bind(at)
+        .bind(key_hash)
+        .fetch_optional(self.pool)
+        .await?;
+
+        row.map(|row| api_key_from_row(&row)).transpose()
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<ApiKey>, PollError> {
+        let rows = sqlx::query("SELECT * FROM api_keys ORDER BY created_at, id")
+            .fetch_all(self.pool)
+            .await?;
+
+        rows.iter().map(api_key_from_row).collect()
+    }
+
+    // Returns whether an active key was revoked.
+    pub async fn revoke(&self, id: Uuid, at: DateTime<Utc>) -> Result<bool, PollError> {
+        let result =
+            sqlx::query("UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+                .bind(at)
+                .bind(id.to_string())
+                .execute(self.pool)
+                .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+impl SessionRepository<'_> {
+    // Expired sessions are removed along the way.
+    pub async fn create(&self, session: &Session, token_hash: &str) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM sessions WHERE expires_at <= $1")
+            .bind(session.created_at)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "
+INSERT INTO sessions (token_hash, user_id, username, guild_ids, created_at, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(token_hash)
+        .bind(session.user_id)
+        .bind(session.username.clone())
+        .bind(session.guild_ids.clone())
+        .bind(session.created_at)
+        .bind(session.expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(&self, token_hash: &str) -> Result<Option<Session>, PollError> {
+        let row = sqlx::query("SELECT * FROM sessions WHERE token_hash = $1")
+            .bind(token_hash)
+            .fetch_optional(self.pool)
+            .await?;
+
+        row.map(|row| session_from_row(&row)).transpose()
+    }
+
+    pub async fn delete_by_hash(&self, token_hash: &str) -> Result<(), PollError> {
+        sqlx::query("DELETE FROM sessions WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
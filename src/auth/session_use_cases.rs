@@ -0,0 +1,60 @@
+use crate::auth::domain::{generate_key, hash_key, Session};
+use crate::auth::repository::SessionRepository;
+use crate::poll::error::PollError;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+// the guilds a user manages are only refreshed when logging in again
+pub const SESSION_DURATION_SECONDS: i64 = 60 * 60 * 24;
+
+pub struct SessionUseCases<'a> {
+    session_repository: SessionRepository<'a>,
+}
+
+impl SessionUseCases<'_> {
+    pub fn new(pool: &'_ PgPool) -> SessionUseCases<'_> {
+        SessionUseCases {
+            session_repository: SessionRepository { pool },
+        }
+    }
+
+    // Returns the session created along with its token, the value of the
+    // session cookie.
+    pub async fn create_session(
+        &self,
+        user_id: i64,
+        username: String,
+        guild_ids: Vec<i64>,
+        at: DateTime<Utc>,
+    ) -> Result<(Session, String), PollError> {
+        let token = generate_key();
+        let session = Session {
+            user_id,
+            username,
+            guild_ids,
+            created_at: at,
+            expires_at: at + Duration::seconds(SESSION_DURATION_SECONDS),
+        };
+        self.session_repository
+            .create(&session, &hash_key(&token))
+            .await?;
+
+        Ok((session, token))
+    }
+
+    // Finds the session of the token, expired and unknown sessions are
+    // unauthorized alike.
+    pub async fn authenticate(&self, token: &str, at: DateTime<Utc>) -> Result<Session, PollError> {
+        self.session_repository
+            .find_by_hash(&hash_key(token))
+            .await?
+            .filter(|session| !session.is_expired(&at))
+            .ok_or_else(|| PollError::Unauthorized(String::from("invalid session")))
+    }
+
+    pub async fn delete_session(&self, token: &str) -> Result<(), PollError> {
+        self.session_repository
+            .delete_by_hash(&hash_key(token))
+            .await
+    }
+}
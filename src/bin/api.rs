@@ -1,11 +1,12 @@
-use axum::{routing::get, Router};
-use cron_poll_discord::api::handlers::{
-    create_poll, delete_poll, get_answers_from_poll, get_poll, get_poll_instance,
-    get_poll_instances, get_polls, update_poll,
-};
+use cron_poll_discord::api::routes::router;
+use cron_poll_discord::api::state::AppState;
+use cron_poll_discord::auth::api_key_use_cases::ApiKeyUseCases;
+use cron_poll_discord::auth::oauth::{OAuthClient, OAuthConfig};
 use cron_poll_discord::migrations::init_db;
 use dotenv::dotenv;
+use serenity::http::Http;
 use std::env;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() {
@@ -13,19 +14,69 @@ async fn main() {
     let database = env::var("DATABASE_URL").expect("Expected DATABASE in the environment");
     let pool = init_db(&database).await.unwrap();
 
-    let app = Router::new()
-        .route("/polls", get(get_polls).post(create_poll))
-        .route(
-            "/polls/{id}",
-            get(get_poll).delete(delete_poll).put(update_poll),
-        )
-        .route("/polls/{id}/instances", get(get_poll_instances))
-        .route("/polls/{id}/instances/{instance}", get(get_poll_instance))
-        .route("/polls/{id}/instances/answers", get(get_answers_from_poll))
-        .with_state(pool);
+    // the first admin key is the one provided, or a generated one shown once
+    let admin_key = env::var("API_ADMIN_KEY").ok().filter(|key| !key.is_empty());
+    let generated = admin_key.is_none();
+    let created = ApiKeyUseCases::new(&pool)
+        .bootstrap_admin_key(admin_key)
+        .await
+        .expect("Could not create the admin api key");
+    match created {
+        Some(key) if generated => println!("admin api key created: {}", key),
+        Some(_) => println!("admin api key created from API_ADMIN_KEY"),
+        None => (),
+    }
+
+    // used to query discord on demand, the api does not connect to the gateway
+    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    let state = AppState {
+        pool,
+        discord: Arc::new(Http::new(&token)),
+        oauth: oauth_config().map(|config| {
+            Arc::new(OAuthClient::new(config).expect("Invalid login with discord configuration"))
+        }),
+    };
+
+    let app = router(state);
 
     let port_api = env::var("PORT_API").expect("Expected PORT_API in the environment");
     let host = "0.0.0.0:".to_owned() + port_api.as_str();
     let listener = tokio::net::TcpListener::bind(host).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
+
+// The login with discord is enabled once the client is configured, the
+// endpoints can be pointed at another provider.
+fn oauth_config() -> Option<OAuthConfig> {
+    let client_id = env::var("OAUTH_CLIENT_ID")
+        .ok()
+        .filter(|id| !id.is_empty())?;
+    let client_secret =
+        env::var("OAUTH_CLIENT_SECRET").expect("Expected OAUTH_CLIENT_SECRET in the environment");
+    let redirect_uri =
+        env::var("OAUTH_REDIRECT_URI").expect("Expected OAUTH_REDIRECT_URI in the environment");
+
+    let mut config = OAuthConfig::new(client_id, client_secret, redirect_uri);
+    if let Ok(url) = env::var("OAUTH_AUTHORIZE_URL") {
+        config.authorize_url = url;
+    }
+    if let Ok(url) = env::var("OAUTH_TOKEN_URL") {
+        config.token_url = url;
+    }
+    if let Ok(url) = env::var("OAUTH_API_URL") {
+        config.api_url = url;
+    }
+    if let Ok(role_ids) = env::var("OAUTH_ROLE_IDS") {
+        config.role_ids = role_ids
+            .split(',')
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| {
+                id.trim()
+                    .parse()
+                    .expect("Invalid role id in OAUTH_ROLE_IDS")
+            })
+            .collect();
+    }
+
+    Some(config)
+}
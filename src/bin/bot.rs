@@ -1,5 +1,6 @@
 use cron_poll_discord::migrations::init_db;
 use cron_poll_discord::poll::poll_instance_use_cases::PollUseCases;
+use cron_poll_discord::poll::vote_manager::{Command, VoteManager};
 use dotenv::dotenv;
 use serenity::async_trait;
 use serenity::model::event::{MessagePollVoteAddEvent, MessagePollVoteRemoveEvent};
@@ -8,45 +9,49 @@ use serenity::prelude::{Context, EventHandler};
 use std::env;
 use tokio::sync::mpsc;
 
-enum Command {
-    Add { poll_id: u64, answer_id: u64 },
-    Remove { poll_id: u64, answer_id: u64 },
-}
-
 struct Handler {
     sender: tokio::sync::mpsc::Sender<Command>,
 }
 
+impl Handler {
+    async fn send(&self, command: Command) {
+        // only fails once the manager is gone, the event is lost either way
+        if let Err(e) = self.sender.send(command).await {
+            eprintln!("[bot] vote dropped {} error=\"{}\"", e.0, e);
+        }
+    }
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn poll_vote_remove(&self, _: Context, msg: MessagePollVoteRemoveEvent) {
         let msg_id = msg.message_id.get();
         let answer_id = msg.answer_id.get();
+        let user_id = msg.user_id.get();
 
         println!("[bot] removing a vote to answer {:?}", answer_id);
 
-        self.sender
-            .send(Command::Remove {
-                poll_id: msg_id,
-                answer_id,
-            })
-            .await
-            .unwrap();
+        self.send(Command::Remove {
+            message_id: msg_id,
+            answer_id,
+            user_id,
+        })
+        .await;
     }
 
     async fn poll_vote_add(&self, _: Context, msg: MessagePollVoteAddEvent) {
         let msg_id = msg.message_id.get();
         let answer_id = msg.answer_id.get();
+        let user_id = msg.user_id.get();
 
         println!("[bot] adding a vote to answer {:?}", answer_id);
 
-        self.sender
-            .send(Command::Add {
-                poll_id: msg_id,
-                answer_id,
-            })
-            .await
-            .unwrap();
+        self.send(Command::Add {
+            message_id: msg_id,
+            answer_id,
+            user_id,
+        })
+        .await;
     }
 }
 
@@ -67,34 +72,10 @@ async fn main() {
     let (tx, mut rx) = mpsc::channel::<Command>(32);
     let manager_pool = pool.clone();
     let manager = tokio::spawn(async move {
-        use Command::*;
-
         println!("starting manager...");
         let poll_use_cases = PollUseCases::new(&manager_pool);
 
-        while let Some(cmd) = rx.recv().await {
-            println!("[manager] new task received");
-            match cmd {
-                Add { poll_id, answer_id } => {
-                    let mut poll =
-                        match poll_use_cases.get_poll_instance_by_id(poll_id as i64).await {
-                            Ok(poll) => poll,
-                            Err(error) => panic!("Could not load poll {:?}", error),
-                        };
-                    poll.add_vote(answer_id as i64).unwrap();
-                    poll_use_cases.save_instance(poll).await.unwrap();
-                }
-                Remove { poll_id, answer_id } => {
-                    let mut poll =
-                        match poll_use_cases.get_poll_instance_by_id(poll_id as i64).await {
-                            Ok(poll) => poll,
-                            Err(error) => panic!("Could not load poll {:?}", error),
-                        };
-                    poll.remove_vote(answer_id as i64).unwrap();
-                    poll_use_cases.save_instance(poll).await.unwrap();
-                }
-            };
-        }
+        VoteManager::new(poll_use_cases).run(&mut rx).await;
     });
 
     let bot = tokio::spawn(async move {
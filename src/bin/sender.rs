@@ -1,22 +1,37 @@
-use chrono::Local;
-use cron_poll_discord::discord::{find_guild_channel, list_guilds};
+use chrono::{DateTime, Timelike, Utc};
+use cron_poll_discord::discord::{error_codes, find_guild_channel, list_guilds, postable_channels};
+use cron_poll_discord::guild::domain::{Channel as DomainChannel, Guild as DomainGuild};
+use cron_poll_discord::guild::guild_use_cases::GuildUseCases;
 use cron_poll_discord::poll::cron_filter;
-use cron_poll_discord::poll::domain::{Poll as DomainPoll, PollInstance, PollInstanceAnswer};
+use cron_poll_discord::poll::domain::{
+    Poll as DomainPoll, PollInstance, PollInstanceAnswer, PollInstanceStatus, PollSend,
+    PollSendFailure,
+};
+use cron_poll_discord::poll::notifications::{POLL_CHANGES_CHANNEL, SEND_REQUESTS_CHANNEL};
 use dotenv::dotenv;
 use serenity::all::create_poll::Ready;
-use serenity::all::{GuildChannel, Message};
+use serenity::all::{ChannelId, ChannelType, Guild, Message, MessageId};
 use serenity::async_trait;
-use serenity::builder::{CreateMessage, CreatePoll, CreatePollAnswer};
+use serenity::builder::{CreateForumPost, CreateMessage, CreatePoll, CreatePollAnswer};
 use serenity::prelude::*;
+use sqlx::postgres::PgListener;
 use sqlx::PgPool;
+use std::collections::HashSet;
 use std::env;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+use uuid::Uuid;
 
 use cron_poll_discord::migrations::init_db;
 use cron_poll_discord::poll::poll_instance_use_cases::PollUseCases;
 
+const MAX_FORUM_POST_NAME_LENGTH: usize = 100;
+const GUILDS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const FINALIZE_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_SCHEDULER_SLEEP: Duration = Duration::from_secs(60);
+
 struct Handler {
     is_running: AtomicBool,
     pool: PgPool,
@@ -54,19 +69,359 @@ fn create_discord_polls(poll: &DomainPoll) -> Vec<CreatePoll<Ready>> {
     return polls;
 }
 
+// What was posted before a send failed, it must not be posted again.
+struct PartialSend {
+    messages: Vec<Message>,
+    // a forum post may have been opened without its message being fetched
+    posted: bool,
+    error: serenity::Error,
+}
+
 async fn send_discord_polls(
     polls: Vec<CreatePoll<Ready>>,
-    channel: &GuildChannel,
+    title: &str,
+    channel_id: ChannelId,
     ctx: &Arc<Context>,
-) -> Vec<Message> {
+) -> Result<Vec<Message>, PartialSend> {
     let mut messages: Vec<Message> = vec![];
+    let mut posted = false;
+
+    match post_discord_polls(polls, title, channel_id, ctx, &mut messages, &mut posted).await {
+        Ok(_) => Ok(messages),
+        Err(error) => Err(PartialSend {
+            posted: posted || !messages.is_empty(),
+            messages,
+            error,
+        }),
+    }
+}
+
+// Forum channels only accept messages inside posts: a post is opened with the
+// first poll and the other ones are sent in it.
+async fn post_discord_polls(
+    polls: Vec<CreatePoll<Ready>>,
+    title: &str,
+    channel_id: ChannelId,
+    ctx: &Arc<Context>,
+    messages: &mut Vec<Message>,
+    posted: &mut bool,
+) -> Result<(), serenity::Error> {
+    let mut polls = polls.into_iter();
+    let mut target = channel_id;
+
+    let channel = channel_id.to_channel(&ctx).await?;
+    let is_forum = channel
+        .guild()
+        .is_some_and(|c| c.kind == ChannelType::Forum);
+
+    if is_forum {
+        if let Some(p) = polls.next() {
+            let name: String = title.chars().take(MAX_FORUM_POST_NAME_LENGTH).collect();
+            let post = channel_id
+                .create_forum_post(
+                    &ctx,
+                    CreateForumPost::new(name, CreateMessage::new().poll(p)),
+                )
+                .await?;
+            *posted = true;
+
+            // the first message of a post shares its id
+            let message = post.id.message(&ctx, MessageId::new(post.id.get())).await?;
+            messages.push(message);
+            target = post.id;
+        }
+    }
 
     for p in polls {
         let poll_msg = CreateMessage::new().poll(p);
-        messages.push(channel.send_message(&ctx, poll_msg).await.unwrap());
+        messages.push(target.send_message(&ctx, poll_msg).await?);
+    }
+
+    Ok(())
+}
+
+// Stores the guilds and channels the bot can post polls into, for the API to
+// list them.
+async fn refresh_guilds(ctx: &Context, pool: &PgPool) {
+    let bot_id = ctx.cache.current_user().id;
+    let guilds = list_guilds(ctx.clone(), ctx.cache.guilds());
+
+    let mut domain_guilds: Vec<DomainGuild> = vec![];
+    let mut domain_channels: Vec<DomainChannel> = vec![];
+    for guild in guilds {
+        domain_guilds.push(DomainGuild {
+            id: guild.id.get() as i64,
+            name: guild.name.clone(),
+        });
+
+        for channel in postable_channels(&guild, bot_id) {
+            domain_channels.push(DomainChannel {
+                id: channel.id.get() as i64,
+                guild_id: guild.id.get() as i64,
+                name: channel.name.clone(),
+                kind: channel.kind.name().to_string(),
+                parent_id: channel.parent_id.map(|id| id.get() as i64),
+            });
+        }
+    }
+
+    let guild_use_cases = GuildUseCases::new(pool);
+    if let Err(e) = guild_use_cases
+        .refresh_guilds(&domain_guilds, &domain_channels)
+        .await
+    {
+        eprintln!("Could not refresh guilds: {:?}", e);
+    }
+}
+
+// Why a send failed.
+struct SendError {
+    error: String,
+    discord_code: Option<i64>,
+    http_status: Option<i32>,
+    // some messages were posted before the failure
+    posted: bool,
+}
+
+impl SendError {
+    fn new(error: String) -> SendError {
+        SendError {
+            error,
+            discord_code: None,
+            http_status: None,
+            posted: false,
+        }
+    }
+
+    fn from_partial_send(partial: &PartialSend) -> SendError {
+        let (discord_code, http_status) = error_codes(&partial.error);
+
+        SendError {
+            error: partial.error.to_string(),
+            discord_code,
+            http_status,
+            posted: partial.posted,
+        }
+    }
+}
+
+// Posts the poll in its channel and saves one instance per message posted.
+async fn send_poll(
+    p: &DomainPoll,
+    send: &PollSend,
+    guilds: &[Guild],
+    ctx: &Arc<Context>,
+    poll_use_cases: &PollUseCases<'_>,
+) -> Result<(), SendError> {
+    let channel_id = match p.channel_id {
+        Some(channel_id) => ChannelId::new(channel_id as u64),
+        None => {
+            // polls created from names before they were resolved by the api,
+            // the ids are stored once found
+            let channels = find_guild_channel(guilds.to_vec(), p.guild.clone(), p.channel.clone());
+
+            if channels.len() == 0 {
+                return Err(SendError::new(format!(
+                    "No channel found for: guild {:?} - channel {:?}",
+                    p.guild, p.channel
+                )));
+            }
+
+            if channels.len() > 1 {
+                return Err(SendError::new(format!(
+                    "Multiple channels found for: guild {:?} - channel {:?}",
+                    p.guild, p.channel
+                )));
+            }
+
+            let channel = &channels[0];
+            if let Err(e) = poll_use_cases
+                .set_poll_discord_ids(p.id, channel.guild_id.get() as i64, channel.id.get() as i64)
+                .await
+            {
+                eprintln!("Could not save discord ids of {:?}: {:?}", p.id, e);
+            }
+
+            channel.id
+        }
+    };
+
+    let polls_to_create = create_discord_polls(p);
+    let created_polls_messages =
+        match send_discord_polls(polls_to_create, &p.question, channel_id, ctx).await {
+            Ok(messages) => messages,
+            Err(partial) => {
+                // the messages posted are tracked even though the send failed
+                save_instances(p, send, partial.messages.clone(), poll_use_cases).await;
+                return Err(SendError::from_partial_send(&partial));
+            }
+        };
+
+    if created_polls_messages.len() == 0 {
+        return Err(SendError::new(format!(
+            "No poll messages created for: guild {:?} - channel {:?}",
+            p.guild, p.channel
+        )));
+    }
+
+    save_instances(p, send, created_polls_messages, poll_use_cases).await;
+
+    Ok(())
+}
+
+// Saves one poll instance per poll message posted.
+async fn save_instances(
+    p: &DomainPoll,
+    send: &PollSend,
+    messages: Vec<Message>,
+    poll_use_cases: &PollUseCases<'_>,
+) {
+    // use the timestamp of the first poll message as the sent_at timestamp for
+    // all poll instances
+    let timestamp = match messages.first() {
+        Some(message) => message.timestamp.unix_timestamp(),
+        None => return,
+    };
+
+    for poll_message in messages {
+        let poll = poll_message.poll.unwrap();
+        let ends_at = poll
+            .expiry
+            .map(|expiry| expiry.unix_timestamp())
+            .unwrap_or(timestamp + p.duration as i64);
+        let answers = poll
+            .answers
+            .into_iter()
+            .map(|a| PollInstanceAnswer {
+                discord_answer_id: a.answer_id.get() as i64,
+                answer: a.poll_media.text.unwrap(),
+                votes: 0,
+            })
+            .collect::<Vec<PollInstanceAnswer>>();
+
+        let instance = PollInstance {
+            id: poll_message.id.get() as i64,
+            sent_at: timestamp,
+            answers,
+            votes: vec![],
+            channel_id: Some(poll_message.channel_id.get() as i64),
+            ends_at: DateTime::from_timestamp(ends_at, 0),
+            status: PollInstanceStatus::Open,
+            finalized_at: None,
+            announced_at: None,
+            occurrence_id: None,
+            scheduled_at: Some(send.scheduled_at),
+            manual: send.manual,
+            poll_uuid: None,
+            poll: Some(p.clone()),
+        };
+
+        if let Err(e) = poll_use_cases.save_instance(instance).await {
+            eprintln!(
+                "Could not save poll instance {:?} of {:?}: {:?}",
+                poll_message.id, p.id, e
+            );
+        }
+    }
+}
+
+// Attempts a send claimed from the ledger, scheduled or requested. Failed
+// attempts are retried with a backoff until the send is given up.
+async fn attempt_send(
+    p: &DomainPoll,
+    send: &PollSend,
+    guilds: &[Guild],
+    ctx: &Arc<Context>,
+    poll_use_cases: &PollUseCases<'_>,
+) {
+    println!(
+        "[sender] sending poll_id={} scheduled_at={} attempt={} manual={}",
+        p.id,
+        send.scheduled_at.to_rfc3339(),
+        send.attempts,
+        send.manual
+    );
+    let error = match send_poll(p, send, guilds, ctx, poll_use_cases).await {
+        Ok(_) => {
+            if let Err(e) = poll_use_cases
+                .complete_poll_send(p.id, send.scheduled_at, Utc::now())
+                .await
+            {
+                eprintln!("Could not record send of poll {:?}: {:?}", p.id, e);
+            }
+            return;
+        }
+        Err(error) => error,
+    };
+
+    let failure = PollSendFailure {
+        id: None,
+        poll_id: p.id,
+        scheduled_at: send.scheduled_at,
+        attempt: send.attempts,
+        error: error.error,
+        discord_code: error.discord_code,
+        http_status: error.http_status,
+        failed_at: Utc::now(),
+        retry_at: None,
+    };
+
+    match poll_use_cases.fail_poll_send(failure, error.posted).await {
+        Ok(failure) => match failure.retry_at {
+            Some(retry_at) => eprintln!(
+                "Could not send poll {:?}, attempt {} failed, retrying at {:?}: {}",
+                p.id, failure.attempt, retry_at, failure.error
+            ),
+            None => eprintln!(
+                "Could not send poll {:?}, giving up after attempt {}: {}",
+                p.id, failure.attempt, failure.error
+            ),
+        },
+        Err(e) => eprintln!("Could not record send failure of poll {:?}: {:?}", p.id, e),
     }
+}
 
-    return messages;
+// Notifications are an optimization, the sender falls back to reloading the
+// polls regularly without them.
+async fn listen_poll_notifications(pool: &PgPool) -> Option<PgListener> {
+    let mut listener = match PgListener::connect_with(pool).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Could not listen to poll notifications: {:?}", e);
+            return None;
+        }
+    };
+
+    match listener
+        .listen_all([POLL_CHANGES_CHANNEL, SEND_REQUESTS_CHANNEL])
+        .await
+    {
+        Ok(_) => Some(listener),
+        Err(e) => {
+            eprintln!("Could not listen to poll notifications: {:?}", e);
+            None
+        }
+    }
+}
+
+// Waits for the api to notify a change, returns whether the polls have to be
+// reloaded.
+async fn wait_for_notification(listener: &mut Option<PgListener>) -> bool {
+    let listener = match listener {
+        Some(listener) => listener,
+        None => return std::future::pending().await,
+    };
+
+    match listener.try_recv().await {
+        Ok(Some(notification)) => notification.channel() == POLL_CHANGES_CHANNEL,
+        // the connection was lost, notifications may have been missed
+        Ok(None) => true,
+        Err(e) => {
+            eprintln!("Could not receive poll notifications: {:?}", e);
+            tokio::time::sleep(MAX_SCHEDULER_SLEEP).await;
+            true
+        }
+    }
 }
 
 #[async_trait]
@@ -79,88 +434,217 @@ impl EventHandler for Handler {
         let pool = Arc::new(self.pool.clone());
 
         if !self.is_running.load(Ordering::Relaxed) {
+            let refresh_ctx = ctx.clone();
+            let refresh_pool = pool.clone();
+            tokio::spawn(async move {
+                loop {
+                    refresh_guilds(&refresh_ctx, &refresh_pool).await;
+                    let _ = tokio::time::sleep(GUILDS_REFRESH_INTERVAL).await;
+                }
+            });
+
+            // votes missed by the bot are caught up from discord
+            let reconcile_ctx = ctx.clone();
+            let reconcile_pool = pool.clone();
             tokio::spawn(async move {
                 loop {
-                    let poll_use_cases = PollUseCases::new(&pool);
-                    let now = Local::now();
-                    let polls =
-                        cron_filter::filter(poll_use_cases.get_unsent_polls().await.unwrap(), &now);
-                    println!("number of polls to send : {:?}", polls.len());
-
-                    for p in polls {
-                        println!("{:?}", p);
-
-                        let channels =
-                            find_guild_channel(guilds.clone(), p.guild.clone(), p.channel.clone());
-
-                        if channels.len() == 0 {
-                            eprintln!(
-                                "No channel found for: guild {:?} - channel {:?}",
-                                p.guild.clone(),
-                                p.channel.clone()
-                            );
-                            continue;
+                    let poll_use_cases = PollUseCases::new(&reconcile_pool);
+                    match poll_use_cases
+                        .reconcile_recent_instances(reconcile_ctx.http.as_ref(), Utc::now())
+                        .await
+                    {
+                        Ok(count) => println!("number of poll instances reconciled : {}", count),
+                        Err(e) => eprintln!("Could not reconcile poll instances: {:?}", e),
+                    }
+                    let _ = tokio::time::sleep(RECONCILE_INTERVAL).await;
+                }
+            });
+
+            // final results are captured once the polls expire, then announced
+            let finalize_ctx = ctx.clone();
+            let finalize_pool = pool.clone();
+            tokio::spawn(async move {
+                loop {
+                    let poll_use_cases = PollUseCases::new(&finalize_pool);
+                    match poll_use_cases
+                        .finalize_expired_instances(finalize_ctx.http.as_ref(), Utc::now())
+                        .await
+                    {
+                        Ok(count) if count > 0 => {
+                            println!("number of poll instances closed : {}", count)
                         }
+                        Ok(_) => (),
+                        Err(e) => eprintln!("Could not finalize poll instances: {:?}", e),
+                    }
 
-                        if channels.len() > 1 {
-                            eprintln!(
-                                "Multiple channels found for: guild {:?} - channel {:?}",
-                                p.guild.clone(),
-                                p.channel.clone()
-                            );
-                            continue;
+                    match poll_use_cases
+                        .announce_results(finalize_ctx.http.as_ref(), Utc::now())
+                        .await
+                    {
+                        Ok(count) if count > 0 => {
+                            println!("number of poll results announced : {}", count)
                         }
+                        Ok(_) => (),
+                        Err(e) => eprintln!("Could not announce poll results: {:?}", e),
+                    }
+                    let _ = tokio::time::sleep(FINALIZE_INTERVAL).await;
+                }
+            });
 
-                        let channel = channels[0].clone();
+            // polls are kept in memory between sends and only reloaded when the
+            // api notifies a change, or after a send updated their state
+            // identifies the sender in the send ledger
+            let sender_id = format!(
+                "{}:{}",
+                env::var("HOSTNAME").unwrap_or_else(|_| String::from("sender")),
+                std::process::id()
+            );
+            tokio::spawn(async move {
+                let mut listener = listen_poll_notifications(&pool).await;
+                let poll_use_cases = PollUseCases::new(&pool);
+                let mut polls: Vec<DomainPoll> = vec![];
+                let mut reload = true;
 
-                        let polls_to_create = create_discord_polls(&p);
-                        let created_polls_messages =
-                            send_discord_polls(polls_to_create, &channel, &ctx).await;
+                loop {
+                    let now = Utc::now().with_nanosecond(0).unwrap();
+
+                    // sends requested through the api, whatever the schedule
+                    match poll_use_cases.claim_send_requests(&sender_id, now).await {
+                        Ok((sends, dropped)) => {
+                            for request in dropped {
+                                eprintln!(
+                                    "[sender] send request dropped id={} poll_id={} requested_at={} reason=already_in_ledger",
+                                    request.id,
+                                    request.poll_id,
+                                    request.requested_at.to_rfc3339()
+                                );
+                            }
+                            for send in sends {
+                                match poll_use_cases.get_poll_by_id(send.poll_id).await {
+                                    Ok(p) => {
+                                        attempt_send(&p, &send, &guilds, &ctx, &poll_use_cases)
+                                            .await;
+                                    }
+                                    Err(e) => eprintln!(
+                                        "Could not send requested poll {:?}: {:?}",
+                                        send.poll_id, e
+                                    ),
+                                }
+                            }
+                        }
+                        Err(e) => eprintln!("Could not claim send requests: {:?}", e),
+                    }
 
-                        if created_polls_messages.len() == 0 {
-                            eprintln!(
-                                "No poll messages created for: guild {:?} - channel {:?}",
-                                p.guild.clone(),
-                                p.channel.clone()
-                            );
-                            continue;
+                    // failed sends whose retry is due
+                    match poll_use_cases.claim_due_send_retries(&sender_id, now).await {
+                        Ok(sends) => {
+                            for send in sends {
+                                match poll_use_cases.get_poll_by_id(send.poll_id).await {
+                                    Ok(p) => {
+                                        attempt_send(&p, &send, &guilds, &ctx, &poll_use_cases)
+                                            .await;
+                                    }
+                                    Err(e) => eprintln!(
+                                        "Could not retry send of poll {:?}: {:?}",
+                                        send.poll_id, e
+                                    ),
+                                }
+                            }
                         }
+                        Err(e) => eprintln!("Could not claim send retries: {:?}", e),
+                    }
 
-                        // use the timestamp of the first poll message as the sent_at timestamp for
-                        // all poll instances
-                        let timestamp = created_polls_messages[0].timestamp.unix_timestamp();
-
-                        // create one poll instance per poll message created
-                        for poll_message in created_polls_messages {
-                            let poll = poll_message.poll.unwrap();
-                            let answers = poll
-                                .answers
-                                .into_iter()
-                                .map(|a| PollInstanceAnswer {
-                                    discord_answer_id: a.answer_id.get() as i64,
-                                    answer: a.poll_media.text.unwrap(),
-                                    votes: 0,
-                                })
-                                .collect::<Vec<PollInstanceAnswer>>();
-
-                            let instance = PollInstance {
-                                id: poll_message.id.get() as i64,
-                                sent_at: timestamp,
-                                answers,
-                                poll_uuid: None,
-                                poll: Some(p.clone()),
-                            };
-
-                            poll_use_cases.save_instance(instance).await.unwrap();
+                    if reload {
+                        match poll_use_cases.get_unsent_polls().await {
+                            Ok(unsent_polls) => {
+                                polls = unsent_polls;
+                                reload = false;
+                            }
+                            Err(e) => eprintln!("Could not load polls: {:?}", e),
                         }
+                    }
 
-                        poll_use_cases
-                            .save_poll(p.clone().sent(true))
+                    // polls whose due sends could not be claimed are evaluated
+                    // again on the next tick rather than losing these sends
+                    let mut unclaimed_ids: HashSet<Uuid> = HashSet::new();
+                    let scheduled_sends = cron_filter::filter(polls.clone(), &now);
+
+                    for scheduled_send in scheduled_sends {
+                        let p = scheduled_send.poll;
+                        let send = PollSend {
+                            poll_id: p.id,
+                            scheduled_at: scheduled_send.scheduled_at,
+                            attempts: 1,
+                            manual: false,
+                        };
+
+                        match poll_use_cases
+                            .claim_poll_send(p.id, send.scheduled_at, &sender_id, Utc::now())
                             .await
-                            .unwrap();
+                        {
+                            Ok(true) => reload = true,
+                            Ok(false) => {
+                                println!(
+                                    "[sender] already sent poll_id={} scheduled_at={}",
+                                    p.id,
+                                    send.scheduled_at.to_rfc3339()
+                                );
+                                reload = true;
+                                continue;
+                            }
+                            Err(e) => {
+                                eprintln!("Could not claim send of poll {:?}: {:?}", p.id, e);
+                                unclaimed_ids.insert(p.id);
+                                continue;
+                            }
+                        }
+
+                        attempt_send(&p, &send, &guilds, &ctx, &poll_use_cases).await;
+                    }
+
+                    let evaluated_ids: Vec<Uuid> = polls
+                        .iter()
+                        .map(|p| p.id)
+                        .filter(|id| !unclaimed_ids.contains(id))
+                        .collect();
+                    match poll_use_cases
+                        .mark_polls_evaluated(&evaluated_ids, now)
+                        .await
+                    {
+                        Ok(_) => polls
+                            .iter_mut()
+                            .filter(|p| !unclaimed_ids.contains(&p.id))
+                            .for_each(|p| p.last_evaluated_at = Some(now)),
+                        Err(e) => eprintln!("Could not save polls evaluation time: {:?}", e),
+                    }
+
+                    // woken up at the next send at the latest, polls are still
+                    // reloaded regularly in case a notification was missed
+                    let next_retry = match poll_use_cases.get_next_send_retry_at().await {
+                        Ok(next_retry) => next_retry,
+                        Err(e) => {
+                            eprintln!("Could not load the next send retry: {:?}", e);
+                            None
+                        }
+                    };
+                    let next_fire = match (cron_filter::next_fire(&polls, &now), next_retry) {
+                        (Some(fire), Some(retry)) => Some(fire.min(retry)),
+                        (fire, retry) => fire.or(retry),
+                    };
+                    let sleep = match next_fire {
+                        Some(at) => (at - Utc::now()).to_std().unwrap_or(Duration::ZERO),
+                        None => MAX_SCHEDULER_SLEEP,
                     }
+                    .min(MAX_SCHEDULER_SLEEP);
 
-                    let _ = tokio::time::sleep(Duration::from_secs(1)).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(sleep) => {
+                            reload = reload || next_fire.is_none_or(|at| at > Utc::now());
+                        }
+                        changed = wait_for_notification(&mut listener) => {
+                            reload = reload || changed;
+                        }
+                    }
                 }
             });
 
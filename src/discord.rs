@@ -1,5 +1,14 @@
-use serenity::all::{Context, Guild, GuildChannel, GuildId};
+use crate::poll::domain::{PollAnswerCount, PollResults};
+use crate::poll::error::PollError;
+use serenity::all::{
+    AnswerId, ChannelId, ChannelType, Context, Guild, GuildChannel, GuildId, Message, MessageId,
+    Permissions, UserId,
+};
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
+use serenity::http::{Http, HttpError};
+use std::future::Future;
 
+// Threads and forum posts are matched as well, they are channels of their own.
 pub fn find_guild_channel(
     guilds: Vec<Guild>,
     guild_name: String,
@@ -9,7 +18,7 @@ pub fn find_guild_channel(
 
     for guild in guilds {
         if guild.name == guild_name {
-            for channel in guild.channels.values() {
+            for channel in guild.channels.values().chain(guild.threads.iter()) {
                 if channel.name == channel_name {
                     result.push(channel.clone());
                 }
@@ -31,10 +40,192 @@ pub fn list_guilds(ctx: Context, ids: Vec<GuildId>) -> Vec<Guild> {
     return guilds;
 }
 
+// Channels of the guild the bot can post polls into. Threads take their
+// permissions from their parent channel.
+pub fn postable_channels(guild: &Guild, bot_id: UserId) -> Vec<GuildChannel> {
+    let mut result: Vec<GuildChannel> = Vec::new();
+
+    let Some(member) = guild.members.get(&bot_id) else {
+        return result;
+    };
+
+    for channel in guild.channels.values() {
+        let postable_kind = matches!(
+            channel.kind,
+            ChannelType::Text | ChannelType::News | ChannelType::Forum
+        );
+        if postable_kind && can_post_polls(guild.user_permissions_in(channel, member), false) {
+            result.push(channel.clone());
+        }
+    }
+
+    for thread in &guild.threads {
+        let parent = thread.parent_id.and_then(|id| guild.channels.get(&id));
+        if let Some(parent) = parent {
+            if can_post_polls(guild.user_permissions_in(parent, member), true) {
+                result.push(thread.clone());
+            }
+        }
+    }
+
+    result
+}
+
+fn can_post_polls(permissions: Permissions, in_thread: bool) -> bool {
+    let send = if in_thread {
+        Permissions::SEND_MESSAGES_IN_THREADS
+    } else {
+        Permissions::SEND_MESSAGES
+    };
+
+    permissions.contains(Permissions::VIEW_CHANNEL | send | Permissions::SEND_POLLS)
+}
+
+// Discord poll endpoints used outside of the gateway, behind a trait so that
+// they can be mocked in tests.
+pub trait DiscordPolls {
+    fn answer_voters(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+        answer_id: i64,
+        after: Option<i64>,
+        limit: u8,
+    ) -> impl Future<Output = Result<Vec<i64>, PollError>> + Send;
+
+    // None when discord did not provide the results of the message.
+    fn poll_results(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<Option<PollResults>, PollError>> + Send;
+
+    fn send_message(
+        &self,
+        channel_id: i64,
+        content: &str,
+    ) -> impl Future<Output = Result<(), PollError>> + Send;
+
+    // Ends the poll of the message right away, returns its results at that
+    // time.
+    fn end_poll(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+    ) -> impl Future<Output = Result<Option<PollResults>, PollError>> + Send;
+}
+
+impl DiscordPolls for Http {
+    async fn answer_voters(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+        answer_id: i64,
+        after: Option<i64>,
+        limit: u8,
+    ) -> Result<Vec<i64>, PollError> {
+        // answer ids are small integers, serenity only builds them from strings
+        let answer_id: AnswerId = answer_id.to_string().parse().map_err(PollError::decode)?;
+
+        let users = self
+            .get_poll_answer_voters(
+                ChannelId::new(channel_id as u64),
+                MessageId::new(message_id as u64),
+                answer_id,
+                after.map(|id| UserId::new(id as u64)),
+                Some(limit),
+            )
+            .await?;
+
+        Ok(users.iter().map(|u| u.id.get() as i64).collect())
+    }
+
+    async fn poll_results(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+    ) -> Result<Option<PollResults>, PollError> {
+        let message = self
+            .get_message(
+                ChannelId::new(channel_id as u64),
+                MessageId::new(message_id as u64),
+            )
+            .await?;
+
+        Ok(message_poll_results(message))
+    }
+
+    // The content is filled with user input, it never pings anyone.
+    async fn send_message(&self, channel_id: i64, content: &str) -> Result<(), PollError> {
+        ChannelId::new(channel_id as u64)
+            .send_message(
+                self,
+                CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // A poll discord already ended is not an error, its results are read
+    // instead.
+    async fn end_poll(
+        &self,
+        channel_id: i64,
+        message_id: i64,
+    ) -> Result<Option<PollResults>, PollError> {
+        let message = self
+            .expire_poll(
+                ChannelId::new(channel_id as u64),
+                MessageId::new(message_id as u64),
+            )
+            .await;
+
+        match message {
+            Ok(message) => Ok(message_poll_results(message)),
+            Err(e) if error_codes(&e).0 == Some(POLL_EXPIRED_CODE) => {
+                self.poll_results(channel_id, message_id).await
+            }
+            Err(e) => Err(PollError::from(e)),
+        }
+    }
+}
+
+// discord error code of the requests made on an ended poll
+const POLL_EXPIRED_CODE: i64 = 520001;
+
+// The discord error code and http status of a failed request, none for the
+// errors not coming from discord.
+pub fn error_codes(e: &serenity::Error) -> (Option<i64>, Option<i32>) {
+    match e {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => (
+            Some(response.error.code as i64),
+            Some(response.status_code.as_u16() as i32),
+        ),
+        _ => (None, None),
+    }
+}
+
+fn message_poll_results(message: Message) -> Option<PollResults> {
+    message.poll.and_then(|p| p.results).map(|r| PollResults {
+        is_finalized: r.is_finalized,
+        answer_counts: r
+            .answer_counts
+            .iter()
+            .map(|c| PollAnswerCount {
+                answer_id: c.id.get() as i64,
+                count: c.count as i32,
+            })
+            .collect(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serenity::all::ChannelId;
+    use serenity::all::{ChannelId, Member};
 
     fn create_guild(name: String, channels: Vec<GuildChannel>) -> Guild {
         let mut guild = Guild::default();
@@ -85,6 +276,71 @@ mod tests {
         assert_eq!(1, got.len())
     }
 
+    #[test]
+    fn matching_thread() {
+        let guild_name: String = "my guild".to_string();
+        let thread_name: String = "my thread".to_string();
+
+        let mut guild = create_guild(
+            guild_name.clone(),
+            vec![create_channel("my channel".to_string(), ChannelId::new(1))],
+        );
+        guild
+            .threads
+            .push(create_channel(thread_name.clone(), ChannelId::new(2)));
+
+        let got = find_guild_channel(vec![guild], guild_name, thread_name);
+
+        assert_eq!(1, got.len());
+        assert_eq!(ChannelId::new(2), got[0].id);
+    }
+
+    #[test]
+    fn poll_permissions() {
+        let channel = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES;
+        assert!(!can_post_polls(channel, false));
+        assert!(can_post_polls(channel | Permissions::SEND_POLLS, false));
+        assert!(!can_post_polls(channel | Permissions::SEND_POLLS, true));
+
+        let thread = Permissions::VIEW_CHANNEL
+            | Permissions::SEND_MESSAGES_IN_THREADS
+            | Permissions::SEND_POLLS;
+        assert!(can_post_polls(thread, true));
+        assert!(!can_post_polls(Permissions::SEND_POLLS, false));
+    }
+
+    #[test]
+    fn postable_channels_of_owner() {
+        let bot_id = UserId::new(42);
+
+        let mut text = create_channel("text".to_string(), ChannelId::new(1));
+        text.kind = ChannelType::Text;
+        let mut voice = create_channel("voice".to_string(), ChannelId::new(2));
+        voice.kind = ChannelType::Voice;
+
+        let mut guild = create_guild("my guild".to_string(), vec![text, voice]);
+        let mut thread = create_channel("thread".to_string(), ChannelId::new(3));
+        thread.kind = ChannelType::PublicThread;
+        thread.parent_id = Some(ChannelId::new(1));
+        guild.threads.push(thread);
+
+        // not a member of the guild
+        assert_eq!(0, postable_channels(&guild, bot_id).len());
+
+        // the owner has every permission
+        let mut member = Member::default();
+        member.user.id = bot_id;
+        guild.members.insert(bot_id, member);
+        guild.owner_id = bot_id;
+
+        let mut got: Vec<ChannelId> = postable_channels(&guild, bot_id)
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        got.sort();
+        assert_eq!(vec![ChannelId::new(1), ChannelId::new(3)], got);
+    }
+
     #[test]
     fn non_matching_guild() {
         let mut guilds: Vec<Guild> = Vec::new();
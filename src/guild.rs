@@ -0,0 +1,3 @@
+pub mod domain;
+pub mod guild_use_cases;
+mod repository;
@@ -0,0 +1,16 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guild {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub id: i64,
+    pub guild_id: i64,
+    pub name: String,
+    // text, news, forum or one of the thread kinds
+    pub kind: String,
+    // category of a channel, channel of a thread
+    pub parent_id: Option<i64>,
+}
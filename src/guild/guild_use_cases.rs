@@ -0,0 +1,46 @@
+use crate::guild::domain::{Channel, Guild};
+use crate::guild::repository::GuildRepository;
+use crate::poll::error::PollError;
+use sqlx::PgPool;
+
+pub struct GuildUseCases<'a> {
+    guild_repository: GuildRepository<'a>,
+}
+
+impl GuildUseCases<'_> {
+    pub fn new(pool: &'_ PgPool) -> GuildUseCases<'_> {
+        GuildUseCases {
+            guild_repository: GuildRepository { pool },
+        }
+    }
+
+    pub async fn get_guilds(&self) -> Result<Vec<Guild>, PollError> {
+        self.guild_repository.get_all().await
+    }
+
+    pub async fn get_guild_channels(&self, guild_id: i64) -> Result<Vec<Channel>, PollError> {
+        self.guild_repository.find_channels(guild_id).await
+    }
+
+    pub async fn find_channels_by_ids(&self, ids: &[i64]) -> Result<Vec<Channel>, PollError> {
+        self.guild_repository.find_channels_by_ids(ids).await
+    }
+
+    pub async fn find_channels_by_name(
+        &self,
+        guild: &str,
+        channel: &str,
+    ) -> Result<Vec<Channel>, PollError> {
+        self.guild_repository
+            .find_channels_by_name(guild, channel)
+            .await
+    }
+
+    pub async fn refresh_guilds(
+        &self,
+        guilds: &[Guild],
+        channels: &[Channel],
+    ) -> Result<(), PollError> {
+        self.guild_repository.replace_all(guilds, channels).await
+    }
+}
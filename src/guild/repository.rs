@@ -0,0 +1,150 @@
+use crate::guild::domain::{Channel, Guild};
+use crate::poll::error::PollError;
+use sqlx::postgres::PgPool;
+use sqlx::Row;
+
+pub struct GuildRepository<'a> {
+    pub pool: &'a PgPool,
+}
+
+impl GuildRepository<'_> {
+    // The whole list is replaced so that guilds the bot left and channels
+    // it lost access to disappear.
+    pub async fn replace_all(
+        &self,
+        guilds: &[Guild],
+        channels: &[Channel],
+    ) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM guilds").execute(&mut *tx).await?;
+
+        for g in guilds {
+            sqlx::query("INSERT INTO guilds (id, name) VALUES ($1, $2)")
+                .bind(g.id)
+                .bind(g.name.clone())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for c in channels {
+            sqlx::query(
+                "INSERT INTO guild_channels (id, guild_id, name, kind, parent_id) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(c.id)
+            .bind(c.guild_id)
+            .bind(c.name.clone())
+            .bind(c.kind.clone())
+            .bind(c.parent_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<Guild>, PollError> {
+        let rows = sqlx::query("SELECT id, name FROM guilds ORDER BY name")
+            .fetch_all(self.pool)
+            .await?;
+
+        let mut guilds: Vec<Guild> = Vec::new();
+        for row in rows {
+            guilds.push(Guild {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+            });
+        }
+
+        Ok(guilds)
+    }
+
+    pub async fn find_channels(&self, guild_id: i64) -> Result<Vec<Channel>, PollError> {
+        let guild = sqlx::query("SELECT id FROM guilds WHERE id = $1")
+            .bind(guild_id)
+            .fetch_optional(self.pool)
+            .await?;
+
+        if guild.is_none() {
+            return Err(PollError::not_found("guild", guild_id));
+        }
+
+        let rows = sqlx::query(
+            "SELECT id, guild_id, name, kind, parent_id FROM guild_channels WHERE guild_id = $1 ORDER BY name",
+        )
+        .bind(guild_id)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut channels: Vec<Channel> = Vec::new();
+        for row in rows {
+            channels.push(Channel {
+                id: row.try_get("id")?,
+                guild_id: row.try_get("guild_id")?,
+                name: row.try_get("name")?,
+                kind: row.try_get("kind")?,
+                parent_id: row.try_get("parent_id")?,
+            });
+        }
+
+        Ok(channels)
+    }
+
+    // Channels named `channel` in the guilds named `guild`, names are not
+    // unique.
+    pub async fn find_channels_by_ids(&self, ids: &[i64]) -> Result<Vec<Channel>, PollError> {
+        let rows = sqlx::query(
+            "SELECT id, guild_id, name, kind, parent_id FROM guild_channels WHERE id = ANY($1) ORDER BY id",
+        )
+        .bind(ids)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut channels: Vec<Channel> = Vec::new();
+        for row in rows {
+            channels.push(Channel {
+                id: row.try_get("id")?,
+                guild_id: row.try_get("guild_id")?,
+                name: row.try_get("name")?,
+                kind: row.try_get("kind")?,
+                parent_id: row.try_get("parent_id")?,
+            });
+        }
+
+        Ok(channels)
+    }
+
+    pub async fn find_channels_by_name(
+        &self,
+        guild: &str,
+        channel: &str,
+    ) -> Result<Vec<Channel>, PollError> {
+        let rows = sqlx::query(
+            "
+SELECT c.id, c.guild_id, c.name, c.kind, c.parent_id
+FROM guild_channels c
+JOIN guilds g ON g.id = c.guild_id
+WHERE g.name = $1 AND c.name = $2
+ORDER BY c.id",
+        )
+        .bind(guild)
+        .bind(channel)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut channels: Vec<Channel> = Vec::new();
+        for row in rows {
+            channels.push(Channel {
+                id: row.try_get("id")?,
+                guild_id: row.try_get("guild_id")?,
+                name: row.try_get("name")?,
+                kind: row.try_get("kind")?,
+                parent_id: row.try_get("parent_id")?,
+            });
+        }
+
+        Ok(channels)
+    }
+}
@@ -1,4 +1,6 @@
 pub mod api;
+pub mod auth;
 pub mod discord;
+pub mod guild;
 pub mod migrations;
 pub mod poll;
@@ -1,4 +1,12 @@
 pub mod cron_filter;
 pub mod domain;
+pub mod error;
+pub mod notifications;
 pub mod poll_instance_use_cases;
+pub mod reconciliation;
 mod repository;
+pub mod search;
+pub mod send_retry;
+pub mod stats;
+pub mod summary;
+pub mod vote_manager;
@@ -1,28 +1,201 @@
-use crate::poll::domain::Poll;
-use chrono::{DateTime, TimeZone};
+use crate::poll::domain::{MisfirePolicy, Poll};
+use crate::poll::error::PollError;
+use chrono::{DateTime, Duration, TimeZone, Timelike, Utc};
+use croner::errors::CronError;
 use croner::Cron;
 
-pub fn filter<Tz: TimeZone>(polls: Vec<Poll>, datetime: &DateTime<Tz>) -> Vec<Poll> {
-    let mut filtered: Vec<Poll> = vec![];
-    let datetime_without_ns =
-        DateTime::parse_from_rfc3339(&datetime.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
-            .unwrap();
+// occurrences older than this when evaluated are considered missed
+const MISFIRE_GRACE_SECONDS: i64 = 60;
+// missed occurrences are never looked for further back than this
+const MAX_CATCH_UP_DAYS: i64 = 7;
+// upper bound of polls sent for a single poll with the fire all policy
+const MAX_CATCH_UP_SENDS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct ScheduledSend {
+    pub poll: Poll,
+    pub scheduled_at: DateTime<Utc>,
+}
+
+// Returns one entry per poll to send at `datetime`, looking for every
+// occurrence since the poll's last evaluation and applying its misfire policy
+// to the ones that were missed.
+pub fn filter<Tz: TimeZone>(polls: Vec<Poll>, datetime: &DateTime<Tz>) -> Vec<ScheduledSend> {
+    let mut filtered: Vec<ScheduledSend> = vec![];
+    let datetime_without_ns = datetime.with_nanosecond(0).unwrap();
 
     for p in polls {
-        if p.onetime && p.sent {
+        if p.is_retired() {
             continue;
         }
 
-        let cron = Cron::new(&p.cron).with_seconds_optional().parse().unwrap();
-        let matching = cron.is_time_matching(&datetime_without_ns).unwrap();
-        if matching {
-            filtered.push(p);
+        let now_utc = datetime_without_ns.with_timezone(&Utc);
+        if p.is_paused(&now_utc) || p.start_at.is_some_and(|start_at| start_at > now_utc) {
+            continue;
+        }
+
+        let timezone: chrono_tz::Tz = match p.timezone.parse() {
+            Ok(tz) => tz,
+            Err(e) => {
+                eprintln!("Invalid timezone for poll {:?}: {:?}", p.id, e);
+                continue;
+            }
+        };
+
+        let now = datetime_without_ns.with_timezone(&timezone);
+        let cron = match parse_cron(&p.cron) {
+            Ok(cron) => cron,
+            Err(e) => {
+                eprintln!("Invalid cron for poll {:?}: {:?}", p.id, e);
+                continue;
+            }
+        };
+        let until = match p.end_at {
+            Some(end_at) => now.min(end_at.with_timezone(&timezone)),
+            None => now,
+        };
+        let occurrences = occurrences_between(&cron, &last_evaluated_at(&p, &now), &until);
+
+        for scheduled_at in apply_misfire_policy(&p, occurrences, &now) {
+            filtered.push(ScheduledSend {
+                poll: p.clone(),
+                scheduled_at,
+            });
         }
     }
 
     return filtered;
 }
 
+// Occurrences are looked for after this date: the last evaluation, bounded by
+// the catch up window, and never before the start of the poll or the end of a
+// pause.
+fn last_evaluated_at(p: &Poll, now: &DateTime<chrono_tz::Tz>) -> DateTime<chrono_tz::Tz> {
+    let previous_second = *now - Duration::seconds(1);
+    let mut oldest = *now - Duration::days(MAX_CATCH_UP_DAYS);
+
+    for not_before in [p.start_at, p.paused_until].into_iter().flatten() {
+        oldest = oldest.max(not_before.with_timezone(&now.timezone()) - Duration::seconds(1));
+    }
+
+    let last_evaluated_at = match p.last_evaluated_at {
+        Some(at) => at.with_timezone(&now.timezone()),
+        None => previous_second,
+    };
+
+    last_evaluated_at.max(oldest).min(previous_second)
+}
+
+// The croner configuration shared by everything reading poll schedules.
+pub fn parse_cron(cron: &str) -> Result<Cron, CronError> {
+    Cron::new(cron).with_seconds_optional().parse()
+}
+
+// The next `count` times the poll will be sent after `from`, in its timezone.
+pub fn upcoming(
+    p: &Poll,
+    from: &DateTime<Utc>,
+    count: usize,
+) -> Result<Vec<DateTime<chrono_tz::Tz>>, PollError> {
+    if p.is_retired() || !p.enabled {
+        return Ok(vec![]);
+    }
+
+    let cron = parse_cron(&p.cron).map_err(PollError::decode)?;
+    let timezone: chrono_tz::Tz = p.timezone.parse().map_err(PollError::decode)?;
+    let count = match p.remaining_occurrences() {
+        Some(remaining) => count.min(remaining as usize),
+        None => count,
+    };
+
+    let mut from = *from;
+    for not_before in [p.start_at, p.paused_until].into_iter().flatten() {
+        from = from.max(not_before - Duration::seconds(1));
+    }
+
+    Ok(occurrences_after(&cron, from.with_timezone(&timezone))
+        .take_while(|occurrence| p.end_at.is_none_or(|end_at| *occurrence <= end_at))
+        .take(count)
+        .collect())
+}
+
+// The earliest time one of the polls is due after `from`, none when none of
+// them will ever be sent again.
+pub fn next_fire(polls: &[Poll], from: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    polls
+        .iter()
+        .filter_map(|p| match upcoming(p, from, 1) {
+            Ok(occurrences) => occurrences.first().map(|o| o.with_timezone(&Utc)),
+            Err(e) => {
+                eprintln!("Invalid schedule for poll {:?}: {:?}", p.id, e);
+                None
+            }
+        })
+        .min()
+}
+
+// Occurrences after `from`. Stepping with `find_next_occurrence` rather than
+// checking `is_time_matching` lets croner resolve DST transitions: a time
+// skipped by a spring-forward gap fires when the gap ends, and a time repeated
+// by a fall-back only fires on its first pass. croner's own iterator is not
+// used as it steps in absolute time, jumping over the local times of a gap.
+fn occurrences_after(
+    cron: &Cron,
+    from: DateTime<chrono_tz::Tz>,
+) -> impl Iterator<Item = DateTime<chrono_tz::Tz>> + '_ {
+    let mut current = from;
+
+    std::iter::from_fn(move || {
+        let next = cron.find_next_occurrence(&current, false).ok()?;
+        if next <= current {
+            return None;
+        }
+
+        current = next;
+        Some(next)
+    })
+}
+
+// Every occurrence in ]from, to].
+fn occurrences_between(
+    cron: &Cron,
+    from: &DateTime<chrono_tz::Tz>,
+    to: &DateTime<chrono_tz::Tz>,
+) -> Vec<DateTime<Utc>> {
+    occurrences_after(cron, *from)
+        .take_while(|occurrence| occurrence <= to)
+        .map(|occurrence| occurrence.with_timezone(&Utc))
+        .collect()
+}
+
+fn apply_misfire_policy(
+    p: &Poll,
+    occurrences: Vec<DateTime<Utc>>,
+    now: &DateTime<chrono_tz::Tz>,
+) -> Vec<DateTime<Utc>> {
+    let grace_limit = *now - Duration::seconds(MISFIRE_GRACE_SECONDS);
+    let latest = match occurrences.last() {
+        Some(latest) => *latest,
+        None => return vec![],
+    };
+
+    let mut scheduled = match p.misfire_policy {
+        MisfirePolicy::FireAll => {
+            let skipped = occurrences.len().saturating_sub(MAX_CATCH_UP_SENDS);
+            occurrences.into_iter().skip(skipped).collect()
+        }
+        MisfirePolicy::FireOnce => vec![latest],
+        MisfirePolicy::Skip if latest >= grace_limit => vec![latest],
+        MisfirePolicy::Skip => vec![],
+    };
+
+    if let Some(remaining) = p.remaining_occurrences() {
+        scheduled.truncate(remaining as usize);
+    }
+
+    scheduled
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -80,4 +253,440 @@ mod tests {
         let result = filter(polls, &datetime);
         assert_eq!(0, result.len());
     }
+
+    // recurring polls are flagged as sent after their first send, they keep
+    // being sent afterwards
+    #[test]
+    fn test_filter_recurring_sent() {
+        let polls: Vec<Poll> = vec![Poll::new().cron(String::from("* * * * *")).sent(true)];
+
+        let date_str = "2020-04-12T22:10:00+02:00";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+    }
+
+    #[test]
+    fn test_filter_timezone() {
+        let polls: Vec<Poll> = vec![
+            Poll::new()
+                .cron(String::from("0 9 * * *"))
+                .timezone(String::from("Europe/Paris")),
+            Poll::new()
+                .cron(String::from("0 9 * * *"))
+                .timezone(String::from("America/New_York")),
+        ];
+
+        // 09:00 in Paris, 03:00 in New York
+        let date_str = "2025-01-15T08:00:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+        assert_eq!("Europe/Paris", result[0].poll.timezone);
+    }
+
+    #[test]
+    fn test_filter_invalid_timezone() {
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("* * * * *"))
+            .timezone(String::from("Mars/Olympus_Mons"))];
+
+        let date_str = "2020-04-12T22:10:00+02:00";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    #[test]
+    fn test_filter_dst_summer_time() {
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .timezone(String::from("Europe/Paris"))];
+
+        // 09:00 CEST
+        let date_str = "2025-07-01T07:00:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls.clone(), &datetime);
+        assert_eq!(1, result.len());
+
+        // 10:00 CEST, which would be 09:00 with a fixed CET offset
+        let date_str = "2025-07-01T08:00:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    #[test]
+    fn test_filter_dst_gap() {
+        // 02:30 does not exist in Paris on 2025-03-30, clocks jump from 02:00 to 03:00
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("30 2 * * *"))
+            .timezone(String::from("Europe/Paris"))];
+
+        let fired: Vec<&str> = [
+            "2025-03-30T00:59:59Z",
+            "2025-03-30T01:00:00Z",
+            "2025-03-30T01:30:00Z",
+        ]
+        .into_iter()
+        .filter(|date_str| {
+            let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+            !filter(polls.clone(), &datetime).is_empty()
+        })
+        .collect();
+
+        // fired once, right when the gap ends (03:00 CEST)
+        assert_eq!(vec!["2025-03-30T01:00:00Z"], fired);
+    }
+
+    #[test]
+    fn test_filter_dst_overlap() {
+        // 02:30 happens twice in Paris on 2025-10-26, clocks go back from 03:00 to 02:00
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("30 2 * * *"))
+            .timezone(String::from("Europe/Paris"))];
+
+        // first pass, 02:30 CEST
+        let date_str = "2025-10-26T00:30:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls.clone(), &datetime);
+        assert_eq!(1, result.len());
+
+        // second pass, 02:30 CET
+        let date_str = "2025-10-26T01:30:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    fn missed_polls(policy: MisfirePolicy) -> Vec<Poll> {
+        // down since 08:59:30, three occurrences were missed
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-15T08:59:30Z").unwrap();
+        vec![Poll::new()
+            .cron(String::from("* * * * *"))
+            .misfire_policy(policy)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))]
+    }
+
+    #[test]
+    fn test_filter_catch_up_fire_once() {
+        let polls = missed_polls(MisfirePolicy::FireOnce);
+
+        let date_str = "2025-01-15T09:02:30Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+        assert_eq!(
+            "2025-01-15T09:02:00+00:00",
+            result[0].scheduled_at.to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn test_filter_catch_up_fire_all() {
+        let polls = missed_polls(MisfirePolicy::FireAll);
+
+        let date_str = "2025-01-15T09:02:30Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        let scheduled: Vec<String> = result.iter().map(|s| s.scheduled_at.to_rfc3339()).collect();
+        assert_eq!(
+            vec![
+                "2025-01-15T09:00:00+00:00",
+                "2025-01-15T09:01:00+00:00",
+                "2025-01-15T09:02:00+00:00",
+            ],
+            scheduled
+        );
+    }
+
+    #[test]
+    fn test_filter_catch_up_fire_all_onetime() {
+        let polls: Vec<Poll> = missed_polls(MisfirePolicy::FireAll)
+            .into_iter()
+            .map(|p| p.onetime(true))
+            .collect();
+
+        let date_str = "2025-01-15T09:02:30Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+    }
+
+    #[test]
+    fn test_filter_catch_up_skip() {
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-15T08:59:30Z").unwrap();
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .misfire_policy(MisfirePolicy::Skip)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))];
+
+        // the occurrence is older than the grace period
+        let date_str = "2025-01-15T09:05:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls.clone(), &datetime);
+        assert_eq!(0, result.len());
+
+        // late by a few seconds only, the occurrence still fires
+        let date_str = "2025-01-15T09:00:05Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+        assert_eq!(
+            "2025-01-15T09:00:00+00:00",
+            result[0].scheduled_at.to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn test_filter_catch_up_nothing_missed() {
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z").unwrap();
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .misfire_policy(MisfirePolicy::FireAll)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))];
+
+        let date_str = "2025-01-15T09:30:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    #[test]
+    fn test_filter_catch_up_across_dst_gap() {
+        // down during the whole night of the switch to summer time
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-03-29T22:00:00Z").unwrap();
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("30 2 * * *"))
+            .timezone(String::from("Europe/Paris"))
+            .misfire_policy(MisfirePolicy::FireAll)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))];
+
+        let date_str = "2025-03-30T06:00:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+        assert_eq!(
+            "2025-03-30T01:00:00+00:00",
+            result[0].scheduled_at.to_rfc3339()
+        );
+    }
+
+    fn to_rfc3339(datetimes: Vec<DateTime<chrono_tz::Tz>>) -> Vec<String> {
+        datetimes.iter().map(|d| d.to_rfc3339()).collect()
+    }
+
+    #[test]
+    fn test_upcoming() {
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * 1-5"))
+            .timezone(String::from("Europe/Paris"));
+
+        // friday 2025-03-28, 12:00 in Paris, right before the switch to summer time
+        let from = DateTime::parse_from_rfc3339("2025-03-28T11:00:00Z").unwrap();
+        let result = upcoming(&poll, &from.with_timezone(&Utc), 3).unwrap();
+        assert_eq!(
+            vec![
+                "2025-03-31T09:00:00+02:00",
+                "2025-04-01T09:00:00+02:00",
+                "2025-04-02T09:00:00+02:00",
+            ],
+            to_rfc3339(result)
+        );
+    }
+
+    #[test]
+    fn test_upcoming_with_seconds() {
+        let poll = Poll::new().cron(String::from("*/20 * * * * *"));
+
+        let from = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z").unwrap();
+        let result = upcoming(&poll, &from.with_timezone(&Utc), 3).unwrap();
+        assert_eq!(
+            vec![
+                "2025-01-15T09:00:20+00:00",
+                "2025-01-15T09:00:40+00:00",
+                "2025-01-15T09:01:00+00:00",
+            ],
+            to_rfc3339(result)
+        );
+    }
+
+    #[test]
+    fn test_upcoming_onetime() {
+        let poll = Poll::new().cron(String::from("0 9 * * *")).onetime(true);
+
+        let from = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z").unwrap();
+        let result = upcoming(&poll, &from.with_timezone(&Utc), 3).unwrap();
+        assert_eq!(vec!["2025-01-16T09:00:00+00:00"], to_rfc3339(result));
+
+        let result = upcoming(&poll.sent(true), &from.with_timezone(&Utc), 3).unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_next_fire() {
+        let from = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let polls: Vec<Poll> = vec![
+            Poll::new().cron(String::from("0 10 * * *")),
+            Poll::new()
+                .cron(String::from("30 9 * * *"))
+                .timezone(String::from("Europe/Paris")),
+            Poll::new().cron(String::from("* * * * *")).enabled(false),
+            Poll::new()
+                .cron(String::from("* * * * *"))
+                .paused_until(from + Duration::minutes(50)),
+        ];
+
+        assert_eq!(
+            Some("2025-01-15T09:50:00+00:00".to_string()),
+            next_fire(&polls, &from).map(|at| at.to_rfc3339())
+        );
+        assert_eq!(
+            Some("2025-01-15T10:00:00+00:00".to_string()),
+            next_fire(&polls[..1], &from).map(|at| at.to_rfc3339())
+        );
+        assert_eq!(None, next_fire(&polls[2..3], &from));
+    }
+
+    #[test]
+    fn test_filter_paused() {
+        let polls: Vec<Poll> = vec![
+            Poll::new().cron(String::from("* * * * *")).enabled(false),
+            Poll::new().cron(String::from("* * * * *")).paused_until(
+                DateTime::parse_from_rfc3339("2020-04-12T22:30:00+02:00")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+        ];
+
+        let date_str = "2020-04-12T22:10:00+02:00";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    #[test]
+    fn test_filter_pause_ended() {
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-15T08:00:00Z").unwrap();
+        let paused_until = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z").unwrap();
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("*/30 * * * *"))
+            .misfire_policy(MisfirePolicy::FireAll)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))
+            .paused_until(paused_until.with_timezone(&Utc))];
+
+        // occurrences during the pause are not caught up
+        let date_str = "2025-01-15T09:30:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        let scheduled: Vec<String> = result.iter().map(|s| s.scheduled_at.to_rfc3339()).collect();
+        assert_eq!(
+            vec!["2025-01-15T09:00:00+00:00", "2025-01-15T09:30:00+00:00"],
+            scheduled
+        );
+    }
+
+    #[test]
+    fn test_upcoming_paused() {
+        let from = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let poll = Poll::new().cron(String::from("0 9 * * *"));
+
+        let result = upcoming(&poll.clone().enabled(false), &from, 3).unwrap();
+        assert!(result.is_empty());
+
+        let result = upcoming(&poll.paused_until(from + Duration::days(2)), &from, 1).unwrap();
+        assert_eq!(vec!["2025-01-17T09:00:00+00:00"], to_rfc3339(result));
+    }
+
+    #[test]
+    fn test_filter_validity_window() {
+        let start_at = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z").unwrap();
+        let end_at = DateTime::parse_from_rfc3339("2025-01-17T09:00:00Z").unwrap();
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .start_at(Some(start_at.with_timezone(&Utc)))
+            .end_at(Some(end_at.with_timezone(&Utc)));
+
+        let fired: Vec<&str> = [
+            "2025-01-14T09:00:00Z",
+            "2025-01-15T09:00:00Z",
+            "2025-01-17T09:00:00Z",
+            "2025-01-18T09:00:00Z",
+        ]
+        .into_iter()
+        .filter(|date_str| {
+            let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+            !filter(vec![poll.clone()], &datetime).is_empty()
+        })
+        .collect();
+
+        assert_eq!(vec!["2025-01-15T09:00:00Z", "2025-01-17T09:00:00Z"], fired);
+    }
+
+    #[test]
+    fn test_filter_catch_up_after_end() {
+        // down since before the end of the poll, the last occurrence is caught up
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-17T08:00:00Z").unwrap();
+        let end_at = DateTime::parse_from_rfc3339("2025-01-17T12:00:00Z").unwrap();
+        let polls: Vec<Poll> = vec![Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))
+            .end_at(Some(end_at.with_timezone(&Utc)))];
+
+        let date_str = "2025-01-18T10:00:00Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(polls, &datetime);
+        assert_eq!(1, result.len());
+        assert_eq!(
+            "2025-01-17T09:00:00+00:00",
+            result[0].scheduled_at.to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn test_filter_max_occurrences() {
+        let last_evaluated_at = DateTime::parse_from_rfc3339("2025-01-15T08:59:30Z").unwrap();
+        let poll = Poll::new()
+            .cron(String::from("* * * * *"))
+            .misfire_policy(MisfirePolicy::FireAll)
+            .last_evaluated_at(last_evaluated_at.with_timezone(&Utc))
+            .max_occurrences(Some(5))
+            .occurrences_sent(3);
+
+        // three occurrences missed, only two remaining
+        let date_str = "2025-01-15T09:02:30Z";
+        let datetime = DateTime::parse_from_rfc3339(date_str).unwrap();
+        let result = filter(vec![poll.clone()], &datetime);
+        assert_eq!(2, result.len());
+
+        let result = filter(vec![poll.occurrences_sent(5)], &datetime);
+        assert_eq!(0, result.len());
+    }
+
+    #[test]
+    fn test_upcoming_validity_window() {
+        let from = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .start_at(Some(from + Duration::days(10)))
+            .end_at(Some(from + Duration::days(20)));
+
+        let result = upcoming(&poll, &from, 100).unwrap();
+        assert_eq!(10, result.len());
+        assert_eq!("2025-01-11T09:00:00+00:00", result[0].to_rfc3339());
+
+        let result = upcoming(
+            &poll.max_occurrences(Some(4)).occurrences_sent(1),
+            &from,
+            100,
+        )
+        .unwrap();
+        assert_eq!(3, result.len());
+    }
 }
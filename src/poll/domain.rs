@@ -1,5 +1,8 @@
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -17,6 +20,125 @@ impl fmt::Display for AnswersError {
     }
 }
 
+// What the sender does with occurrences it missed while it was down or late.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    // send a single poll for all the missed occurrences
+    #[default]
+    FireOnce,
+    // send one poll per missed occurrence
+    FireAll,
+    // drop missed occurrences
+    Skip,
+}
+
+impl fmt::Display for MisfirePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FireOnce => write!(f, "fire_once"),
+            Self::FireAll => write!(f, "fire_all"),
+            Self::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+impl FromStr for MisfirePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fire_once" => Ok(Self::FireOnce),
+            "fire_all" => Ok(Self::FireAll),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("unknown misfire policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PollInstanceStatus {
+    // discord still accepts votes
+    #[default]
+    Open,
+    // the poll has ended, the votes are final
+    Closed,
+}
+
+impl fmt::Display for PollInstanceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => write!(f, "open"),
+            Self::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl FromStr for PollInstanceStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            _ => Err(format!("unknown poll instance status: {}", s)),
+        }
+    }
+}
+
+// State of a scheduled send in the send ledger.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollSendStatus {
+    // a sender is posting the poll, or crashed while doing so
+    Claimed,
+    Sent,
+    // the last attempt failed, the send is attempted again later
+    Retrying,
+    // every attempt failed
+    Failed,
+}
+
+impl fmt::Display for PollSendStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Claimed => write!(f, "claimed"),
+            Self::Sent => write!(f, "sent"),
+            Self::Retrying => write!(f, "retrying"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for PollSendStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "claimed" => Ok(Self::Claimed),
+            "sent" => Ok(Self::Sent),
+            "retrying" => Ok(Self::Retrying),
+            "failed" => Ok(Self::Failed),
+            _ => Err(format!("unknown poll send status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollAnswerCount {
+    pub answer_id: i64,
+    pub count: i32,
+}
+
+// Results of a poll message as reported by discord, answers without votes
+// may be missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollResults {
+    pub is_finalized: bool,
+    pub answer_counts: Vec<PollAnswerCount>,
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct PollInstanceAnswer {
     pub answer: String,
@@ -24,15 +146,113 @@ pub struct PollInstanceAnswer {
     pub votes: i32,
 }
 
+// A discord user voting for an answer, the answer votes are counted from them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollVote {
+    pub answer_id: i64,
+    pub user_id: i64,
+}
+
+// Votes to write to go from the stored votes to the new ones, the votes are
+// written one by one so that concurrent writers keep each other's votes.
+#[derive(Debug, Default, PartialEq)]
+pub struct VoteChanges {
+    pub added: Vec<PollVote>,
+    pub removed: Vec<PollVote>,
+}
+
+impl VoteChanges {
+    pub fn between(before: &[PollVote], after: &[PollVote]) -> VoteChanges {
+        VoteChanges {
+            added: after
+                .iter()
+                .filter(|v| !before.contains(v))
+                .cloned()
+                .collect(),
+            removed: before
+                .iter()
+                .filter(|v| !after.contains(v))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PollInstance {
     pub id: i64,
     pub sent_at: i64,
     pub answers: Vec<PollInstanceAnswer>,
+    pub votes: Vec<PollVote>,
+    pub channel_id: Option<i64>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub status: PollInstanceStatus,
+    // set once the final results have been fetched from discord
+    pub finalized_at: Option<DateTime<Utc>>,
+    // set once the results summary has been posted
+    pub announced_at: Option<DateTime<Utc>>,
+    // set by the repository, the occurrence is created along with its first
+    // message
+    pub occurrence_id: Option<i64>,
+    // set by the sender, the send the message was posted by, which the
+    // occurrence is keyed by
+    pub scheduled_at: Option<DateTime<Utc>>,
+    // sent on request rather than by the schedule
+    pub manual: bool,
     pub poll_uuid: Option<Uuid>,
     pub poll: Option<Poll>,
 }
 
+// A send of a poll. Discord polls are limited to 10 answers, the answers are
+// split into one message per chunk of 10, each being a poll instance.
+#[derive(Debug, Clone)]
+pub struct PollOccurrence {
+    pub id: i64,
+    pub poll_id: Uuid,
+    pub sent_at: i64,
+    pub instances: Vec<PollInstance>,
+}
+
+// A send of the poll claimed from the send ledger. Requested sends are keyed
+// by the date they were requested at.
+#[derive(Debug, Clone)]
+pub struct PollSend {
+    pub poll_id: Uuid,
+    pub scheduled_at: DateTime<Utc>,
+    pub attempts: i32,
+    // requested through the api rather than scheduled
+    pub manual: bool,
+}
+
+// A failed attempt at sending the poll.
+#[derive(Debug, Clone)]
+pub struct PollSendFailure {
+    // none until stored, and for a send left claimed
+    pub id: Option<i64>,
+    pub poll_id: Uuid,
+    pub scheduled_at: DateTime<Utc>,
+    pub attempt: i32,
+    pub error: String,
+    pub discord_code: Option<i64>,
+    pub http_status: Option<i32>,
+    pub failed_at: DateTime<Utc>,
+    // none when the send was given up
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+// A send of the poll requested out of its schedule.
+#[derive(Debug, Clone)]
+pub struct PollSendRequest {
+    pub id: i64,
+    pub poll_id: Uuid,
+    pub requested_at: DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Poll {
     pub cron: String,
@@ -42,9 +262,26 @@ pub struct Poll {
     pub multiselect: bool,
     pub guild: String,
     pub channel: String,
+    pub guild_id: Option<i64>,
+    pub channel_id: Option<i64>,
     pub duration: i32,
     pub onetime: bool,
     pub sent: bool,
+    pub timezone: String,
+    pub misfire_policy: MisfirePolicy,
+    pub last_evaluated_at: Option<DateTime<Utc>>,
+    pub enabled: bool,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub start_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub max_occurrences: Option<i32>,
+    pub occurrences_sent: i32,
+    pub expose_voters: bool,
+    pub announce_results: bool,
+    // the default summary is used when not set
+    pub results_template: Option<String>,
+    pub results_channel_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
 }
 
 impl Poll {
@@ -57,9 +294,25 @@ impl Poll {
             multiselect: false,
             guild: "".to_string(),
             channel: "".to_string(),
+            guild_id: None,
+            channel_id: None,
             duration: 0,
             onetime: false,
             sent: false,
+            timezone: "UTC".to_string(),
+            misfire_policy: MisfirePolicy::default(),
+            last_evaluated_at: None,
+            enabled: true,
+            paused_until: None,
+            start_at: None,
+            end_at: None,
+            max_occurrences: None,
+            occurrences_sent: 0,
+            expose_voters: false,
+            announce_results: false,
+            results_template: None,
+            results_channel_id: None,
+            created_at: Utc::now(),
         }
     }
 
@@ -98,6 +351,16 @@ impl Poll {
         return self;
     }
 
+    pub fn guild_id(mut self, guild_id: Option<i64>) -> Self {
+        self.guild_id = guild_id;
+        return self;
+    }
+
+    pub fn channel_id(mut self, channel_id: Option<i64>) -> Self {
+        self.channel_id = channel_id;
+        return self;
+    }
+
     pub fn duration(mut self, duration: i32) -> Self {
         self.duration = duration;
         return self;
@@ -112,6 +375,94 @@ impl Poll {
         self.sent = sent;
         return self;
     }
+
+    pub fn timezone(mut self, timezone: String) -> Self {
+        self.timezone = timezone;
+        return self;
+    }
+
+    pub fn misfire_policy(mut self, misfire_policy: MisfirePolicy) -> Self {
+        self.misfire_policy = misfire_policy;
+        return self;
+    }
+
+    pub fn last_evaluated_at(mut self, last_evaluated_at: DateTime<Utc>) -> Self {
+        self.last_evaluated_at = Some(last_evaluated_at);
+        return self;
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        return self;
+    }
+
+    pub fn paused_until(mut self, paused_until: DateTime<Utc>) -> Self {
+        self.paused_until = Some(paused_until);
+        return self;
+    }
+
+    pub fn start_at(mut self, start_at: Option<DateTime<Utc>>) -> Self {
+        self.start_at = start_at;
+        return self;
+    }
+
+    pub fn end_at(mut self, end_at: Option<DateTime<Utc>>) -> Self {
+        self.end_at = end_at;
+        return self;
+    }
+
+    pub fn max_occurrences(mut self, max_occurrences: Option<i32>) -> Self {
+        self.max_occurrences = max_occurrences;
+        return self;
+    }
+
+    pub fn expose_voters(mut self, expose_voters: bool) -> Self {
+        self.expose_voters = expose_voters;
+        return self;
+    }
+
+    pub fn announce_results(mut self, announce_results: bool) -> Self {
+        self.announce_results = announce_results;
+        return self;
+    }
+
+    pub fn results_template(mut self, results_template: Option<String>) -> Self {
+        self.results_template = results_template;
+        return self;
+    }
+
+    pub fn results_channel_id(mut self, results_channel_id: Option<i64>) -> Self {
+        self.results_channel_id = results_channel_id;
+        return self;
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        return self;
+    }
+
+    pub fn occurrences_sent(mut self, occurrences_sent: i32) -> Self {
+        self.occurrences_sent = occurrences_sent;
+        return self;
+    }
+
+    // How many more times the poll can be sent, None when unlimited.
+    pub fn remaining_occurrences(&self) -> Option<i32> {
+        if self.onetime {
+            return Some(if self.sent { 0 } else { 1 });
+        }
+
+        self.max_occurrences
+            .map(|max| (max - self.occurrences_sent).max(0))
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.remaining_occurrences() == Some(0)
+    }
+
+    pub fn is_paused(&self, at: &DateTime<Utc>) -> bool {
+        !self.enabled || self.paused_until.is_some_and(|until| until > *at)
+    }
 }
 
 impl PollInstance {
@@ -120,54 +471,164 @@ impl PollInstance {
             id: 0,
             sent_at: 0,
             answers: vec![],
+            votes: vec![],
+            channel_id: None,
+            ends_at: None,
+            status: PollInstanceStatus::Open,
+            finalized_at: None,
+            announced_at: None,
+            occurrence_id: None,
+            scheduled_at: None,
+            manual: false,
             poll_uuid: None,
             poll: Some(p),
         }
     }
 
-    pub fn add_vote(&mut self, vote_id: i64) -> Result<(), AnswersError> {
-        if self.answers.is_empty() {
-            return Err(AnswersError::Empty);
+    // Voting twice for the same answer counts once, so replayed events do
+    // not change the results.
+    pub fn add_vote(&mut self, answer_id: i64, user_id: i64) -> Result<(), AnswersError> {
+        self.find_answer(answer_id)?;
+
+        let vote = PollVote { answer_id, user_id };
+        if !self.votes.contains(&vote) {
+            self.votes.push(vote);
         }
+        self.count_votes();
+
+        Ok(())
+    }
+
+    pub fn remove_vote(&mut self, answer_id: i64, user_id: i64) -> Result<(), AnswersError> {
+        self.find_answer(answer_id)?;
 
-        let mut found = false;
+        self.votes
+            .retain(|v| v.answer_id != answer_id || v.user_id != user_id);
+        self.count_votes();
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self, at: &DateTime<Utc>) -> bool {
+        self.ends_at.is_some_and(|ends_at| ends_at <= *at)
+    }
+
+    // Closes the instance with the final counts from discord.
+    pub fn finalize(&mut self, results: &PollResults, at: DateTime<Utc>) {
         for answer in self.answers.iter_mut() {
-            if answer.discord_answer_id == vote_id {
-                answer.votes = answer.votes + 1;
-                found = true;
-            }
+            answer.votes = results
+                .answer_counts
+                .iter()
+                .find(|c| c.answer_id == answer.discord_answer_id)
+                .map_or(0, |c| c.count);
         }
 
-        if !found {
-            return Err(AnswersError::NotFound);
+        self.status = PollInstanceStatus::Closed;
+        self.finalized_at = Some(at);
+    }
+
+    // Closes the instance keeping the counts known so far.
+    pub fn close(&mut self) {
+        self.status = PollInstanceStatus::Closed;
+    }
+
+    // Closes the instance before it expires, with the counts discord had when
+    // the poll was ended.
+    pub fn end_early(&mut self, results: Option<&PollResults>, at: DateTime<Utc>) {
+        match results {
+            Some(results) => self.finalize(results, at),
+            None => {
+                self.close();
+                self.finalized_at = Some(at);
+            }
         }
+        self.ends_at = Some(at);
+    }
 
-        Ok(())
+    // Replaces the votes with the ones known by discord, returns whether the
+    // stored ones had drifted.
+    pub fn reconcile_votes(&mut self, mut votes: Vec<PollVote>) -> bool {
+        let mut current = self.votes.clone();
+        let key = |v: &PollVote| (v.answer_id, v.user_id);
+        current.sort_by_key(key);
+        votes.sort_by_key(key);
+        votes.dedup();
+
+        let counts: Vec<i32> = self.answers.iter().map(|a| a.votes).collect();
+
+        self.votes = votes;
+        self.count_votes();
+
+        current != self.votes || counts != self.answers.iter().map(|a| a.votes).collect::<Vec<_>>()
     }
 
-    pub fn remove_vote(&mut self, vote_id: i64) -> Result<(), AnswersError> {
+    pub fn voters(&self, answer_id: i64) -> Vec<i64> {
+        self.votes
+            .iter()
+            .filter(|v| v.answer_id == answer_id)
+            .map(|v| v.user_id)
+            .collect()
+    }
+
+    fn find_answer(&self, answer_id: i64) -> Result<&PollInstanceAnswer, AnswersError> {
         if self.answers.is_empty() {
             return Err(AnswersError::Empty);
         }
 
-        let mut found = false;
+        self.answers
+            .iter()
+            .find(|a| a.discord_answer_id == answer_id)
+            .ok_or(AnswersError::NotFound)
+    }
+
+    fn count_votes(&mut self) {
         for answer in self.answers.iter_mut() {
-            if answer.discord_answer_id == vote_id {
-                if answer.votes - 1 > 0 {
-                    answer.votes = answer.votes - 1;
-                } else {
-                    answer.votes = 0;
-                }
-
-                found = true;
-            }
+            answer.votes = self
+                .votes
+                .iter()
+                .filter(|v| v.answer_id == answer.discord_answer_id)
+                .count() as i32;
         }
+    }
+}
+
+impl PollOccurrence {
+    // The answers of every message, in the order they were sent.
+    pub fn answers(&self) -> Vec<PollInstanceAnswer> {
+        self.instances
+            .iter()
+            .flat_map(|i| i.answers.iter().cloned())
+            .collect()
+    }
 
-        if !found {
-            return Err(AnswersError::NotFound);
+    // Closed once every message is.
+    pub fn status(&self) -> PollInstanceStatus {
+        if self
+            .instances
+            .iter()
+            .all(|i| i.status == PollInstanceStatus::Closed)
+        {
+            PollInstanceStatus::Closed
+        } else {
+            PollInstanceStatus::Open
         }
+    }
 
-        Ok(())
+    pub fn ends_at(&self) -> Option<DateTime<Utc>> {
+        self.instances.iter().filter_map(|i| i.ends_at).max()
+    }
+
+    pub fn is_manual(&self) -> bool {
+        self.instances.iter().any(|i| i.manual)
+    }
+
+    // Set once the results of every message are final.
+    pub fn finalized_at(&self) -> Option<DateTime<Utc>> {
+        self.instances
+            .iter()
+            .map(|i| i.finalized_at)
+            .collect::<Option<Vec<DateTime<Utc>>>>()
+            .and_then(|dates| dates.into_iter().max())
     }
 }
 
@@ -175,6 +636,39 @@ impl PollInstance {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_paused() {
+        let now = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert!(!Poll::new().is_paused(&now));
+        assert!(Poll::new().enabled(false).is_paused(&now));
+        assert!(Poll::new()
+            .paused_until(now + chrono::Duration::hours(1))
+            .is_paused(&now));
+        assert!(!Poll::new().paused_until(now).is_paused(&now));
+    }
+
+    #[test]
+    fn test_remaining_occurrences() {
+        assert_eq!(None, Poll::new().remaining_occurrences());
+        assert_eq!(None, Poll::new().sent(true).remaining_occurrences());
+        assert_eq!(Some(1), Poll::new().onetime(true).remaining_occurrences());
+        assert_eq!(
+            Some(0),
+            Poll::new().onetime(true).sent(true).remaining_occurrences()
+        );
+
+        let poll = Poll::new().max_occurrences(Some(3)).occurrences_sent(2);
+        assert_eq!(Some(1), poll.remaining_occurrences());
+        assert!(!poll.is_retired());
+
+        let poll = poll.occurrences_sent(3);
+        assert_eq!(Some(0), poll.remaining_occurrences());
+        assert!(poll.is_retired());
+    }
+
     #[test]
     fn test_add_vote() {
         let p = Poll::new();
@@ -187,7 +681,7 @@ mod tests {
 
         assert_eq!(0, poll.answers[0].votes);
 
-        let result = poll.add_vote(0);
+        let result = poll.add_vote(0, 42);
         assert_eq!(1, poll.answers[0].votes);
         assert_eq!(true, result.is_ok())
     }
@@ -196,7 +690,7 @@ mod tests {
     fn test_add_vote_no_answers() {
         let p = Poll::new();
         let mut poll = PollInstance::new(p);
-        let result = poll.add_vote(0);
+        let result = poll.add_vote(0, 42);
         assert_eq!(true, result.is_err())
     }
 
@@ -210,7 +704,7 @@ mod tests {
             votes: 0,
         }];
 
-        let result = poll.add_vote(2);
+        let result = poll.add_vote(2, 42);
         assert_eq!(true, result.is_err())
     }
 
@@ -235,12 +729,251 @@ mod tests {
                 votes: 0,
             },
         ];
-        let result = poll.add_vote(2);
+        let result = poll.add_vote(2, 42);
         assert_eq!(false, result.is_err());
         assert_eq!(1, poll.answers[2].votes);
 
-        let result = poll.add_vote(0);
+        let result = poll.add_vote(0, 42);
         assert_eq!(false, result.is_err());
         assert_eq!(1, poll.answers[0].votes);
     }
+
+    #[test]
+    fn test_add_vote_replayed() {
+        let p = Poll::new();
+        let mut poll = PollInstance::new(p);
+        poll.answers = vec![PollInstanceAnswer {
+            discord_answer_id: 0,
+            answer: String::new(),
+            votes: 0,
+        }];
+
+        poll.add_vote(0, 42).unwrap();
+        poll.add_vote(0, 42).unwrap();
+        assert_eq!(1, poll.answers[0].votes);
+
+        poll.add_vote(0, 43).unwrap();
+        assert_eq!(2, poll.answers[0].votes);
+        assert_eq!(vec![42, 43], poll.voters(0));
+    }
+
+    #[test]
+    fn test_reconcile_votes() {
+        let p = Poll::new();
+        let mut poll = PollInstance::new(p);
+        poll.answers = vec![
+            PollInstanceAnswer {
+                discord_answer_id: 1,
+                answer: String::new(),
+                votes: 0,
+            },
+            PollInstanceAnswer {
+                discord_answer_id: 2,
+                answer: String::new(),
+                votes: 0,
+            },
+        ];
+        poll.add_vote(1, 42).unwrap();
+
+        // a vote was missed and another one was not removed
+        let changed = poll.reconcile_votes(vec![
+            PollVote {
+                answer_id: 2,
+                user_id: 43,
+            },
+            PollVote {
+                answer_id: 2,
+                user_id: 42,
+            },
+        ]);
+        assert!(changed);
+        assert_eq!(0, poll.answers[0].votes);
+        assert_eq!(2, poll.answers[1].votes);
+        assert_eq!(vec![42, 43], poll.voters(2));
+
+        let changed = poll.reconcile_votes(vec![
+            PollVote {
+                answer_id: 2,
+                user_id: 43,
+            },
+            PollVote {
+                answer_id: 2,
+                user_id: 42,
+            },
+        ]);
+        assert!(!changed);
+    }
+
+    #[test]
+    fn test_vote_changes() {
+        let vote = |answer_id: i64, user_id: i64| PollVote { answer_id, user_id };
+
+        let changes =
+            VoteChanges::between(&[vote(1, 42), vote(2, 43)], &[vote(2, 43), vote(2, 42)]);
+        assert_eq!(vec![vote(2, 42)], changes.added);
+        assert_eq!(vec![vote(1, 42)], changes.removed);
+
+        assert_eq!(
+            true,
+            VoteChanges::between(&[vote(1, 42)], &[vote(1, 42)]).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_reconcile_counters() {
+        let p = Poll::new();
+        let mut poll = PollInstance::new(p);
+        poll.answers = vec![PollInstanceAnswer {
+            discord_answer_id: 1,
+            answer: String::new(),
+            votes: 3,
+        }];
+
+        // counts from before the votes were recorded are replaced too
+        assert!(poll.reconcile_votes(vec![]));
+        assert_eq!(0, poll.answers[0].votes);
+    }
+
+    #[test]
+    fn test_finalize() {
+        let now = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut poll = PollInstance::new(Poll::new());
+        poll.ends_at = Some(now);
+        poll.answers = vec![
+            PollInstanceAnswer {
+                discord_answer_id: 1,
+                answer: String::new(),
+                votes: 1,
+            },
+            PollInstanceAnswer {
+                discord_answer_id: 2,
+                answer: String::new(),
+                votes: 1,
+            },
+        ];
+
+        assert!(!poll.is_expired(&(now - chrono::Duration::seconds(1))));
+        assert!(poll.is_expired(&now));
+
+        poll.finalize(
+            &PollResults {
+                is_finalized: true,
+                answer_counts: vec![PollAnswerCount {
+                    answer_id: 1,
+                    count: 3,
+                }],
+            },
+            now,
+        );
+
+        assert_eq!(3, poll.answers[0].votes);
+        assert_eq!(0, poll.answers[1].votes);
+        assert_eq!(PollInstanceStatus::Closed, poll.status);
+        assert_eq!(Some(now), poll.finalized_at);
+    }
+
+    #[test]
+    fn test_occurrence() {
+        let now = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut first = PollInstance::new(Poll::new());
+        first.answers = vec![PollInstanceAnswer {
+            discord_answer_id: 1,
+            answer: String::from("first"),
+            votes: 2,
+        }];
+        first.ends_at = Some(now);
+        first.close();
+        first.finalized_at = Some(now);
+
+        let mut second = PollInstance::new(Poll::new());
+        second.answers = vec![PollInstanceAnswer {
+            discord_answer_id: 1,
+            answer: String::from("second"),
+            votes: 1,
+        }];
+        second.ends_at = Some(now + chrono::Duration::seconds(1));
+
+        let mut occurrence = PollOccurrence {
+            id: 1,
+            poll_id: Uuid::new_v4(),
+            sent_at: 0,
+            instances: vec![first, second],
+        };
+
+        let answers: Vec<String> = occurrence.answers().into_iter().map(|a| a.answer).collect();
+        assert_eq!(vec!["first", "second"], answers);
+        assert_eq!(PollInstanceStatus::Open, occurrence.status());
+        assert_eq!(
+            Some(now + chrono::Duration::seconds(1)),
+            occurrence.ends_at()
+        );
+        assert_eq!(None, occurrence.finalized_at());
+
+        occurrence.instances[1].end_early(None, now);
+        assert_eq!(PollInstanceStatus::Closed, occurrence.status());
+        assert_eq!(Some(now), occurrence.finalized_at());
+    }
+
+    #[test]
+    fn test_end_early() {
+        let now = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut poll = PollInstance::new(Poll::new());
+        poll.ends_at = Some(now + chrono::Duration::hours(1));
+        poll.answers = vec![PollInstanceAnswer {
+            discord_answer_id: 1,
+            answer: String::new(),
+            votes: 0,
+        }];
+        poll.add_vote(1, 100).unwrap();
+
+        poll.end_early(None, now);
+
+        assert_eq!(1, poll.answers[0].votes);
+        assert_eq!(PollInstanceStatus::Closed, poll.status);
+        assert_eq!(Some(now), poll.finalized_at);
+        assert_eq!(Some(now), poll.ends_at);
+        assert!(poll.is_expired(&now));
+    }
+
+    #[test]
+    fn test_remove_vote() {
+        let p = Poll::new();
+        let mut poll = PollInstance::new(p);
+        poll.answers = vec![
+            PollInstanceAnswer {
+                discord_answer_id: 0,
+                answer: String::new(),
+                votes: 0,
+            },
+            PollInstanceAnswer {
+                discord_answer_id: 1,
+                answer: String::new(),
+                votes: 0,
+            },
+        ];
+
+        poll.add_vote(0, 42).unwrap();
+        poll.add_vote(1, 42).unwrap();
+        poll.add_vote(0, 43).unwrap();
+
+        poll.remove_vote(0, 42).unwrap();
+        assert_eq!(1, poll.answers[0].votes);
+        assert_eq!(1, poll.answers[1].votes);
+        assert_eq!(vec![43], poll.voters(0));
+
+        // removing a vote that was never recorded leaves the counts untouched
+        poll.remove_vote(1, 43).unwrap();
+        assert_eq!(1, poll.answers[1].votes);
+
+        assert!(poll.remove_vote(2, 42).is_err());
+    }
 }
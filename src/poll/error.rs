@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema, Debug, PartialEq, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: String) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PollError {
+    NotFound(String),
+    // no valid credentials were provided
+    Unauthorized(String),
+    Forbidden(String),
+    Validation(Vec<FieldError>),
+    Conflict(String),
+    Database(sqlx::Error),
+    Discord(Box<serenity::Error>),
+}
+
+impl PollError {
+    pub fn not_found(what: &str, id: impl fmt::Display) -> PollError {
+        PollError::NotFound(format!("{} {} not found", what, id))
+    }
+
+    // Stored data that could not be turned back into the domain, e.g. a
+    // malformed uuid.
+    pub fn decode(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> PollError {
+        PollError::Database(sqlx::Error::Decode(e.into()))
+    }
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(message) => write!(f, "{}", message),
+            Self::Unauthorized(message) => write!(f, "{}", message),
+            Self::Forbidden(message) => write!(f, "{}", message),
+            Self::Validation(errors) => write!(f, "invalid input ({} errors)", errors.len()),
+            Self::Conflict(message) => write!(f, "{}", message),
+            Self::Database(e) => write!(f, "database error: {}", e),
+            Self::Discord(e) => write!(f, "discord error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PollError {}
+
+impl From<sqlx::Error> for PollError {
+    fn from(e: sqlx::Error) -> Self {
+        PollError::Database(e)
+    }
+}
+
+impl From<serenity::Error> for PollError {
+    fn from(e: serenity::Error) -> Self {
+        PollError::Discord(Box::new(e))
+    }
+}
@@ -0,0 +1,8 @@
+// Postgres channels the api notifies the sender on, the payload is the poll id.
+// Notifications are only hints to wake the sender up, it still reads the
+// state from the database.
+
+// a poll was created, updated, deleted, paused or resumed
+pub const POLL_CHANGES_CHANNEL: &str = "poll_changes";
+// a send of a poll was requested
+pub const SEND_REQUESTS_CHANNEL: &str = "poll_send_requests";
@@ -1,7 +1,21 @@
-use crate::poll::domain::{Poll, PollInstance, PollInstanceAnswer};
+use crate::discord::DiscordPolls;
+use crate::poll::domain::{
+    Poll, PollInstance, PollInstanceAnswer, PollInstanceStatus, PollOccurrence, PollResults,
+    PollSend, PollSendFailure, PollSendRequest, PollVote, VoteChanges,
+};
+use crate::poll::error::PollError;
+use crate::poll::notifications::{POLL_CHANGES_CHANNEL, SEND_REQUESTS_CHANNEL};
+use crate::poll::reconciliation::{
+    end_instance, fetch_votes, ANNOUNCE_AFTER_CLOSE_SECONDS, FINALIZE_GIVE_UP_SECONDS,
+    RECONCILE_AFTER_CLOSE_SECONDS,
+};
 use crate::poll::repository::{PollInstanceRepository, PollRepository};
+use crate::poll::search::{PollCursor, PollFilter, PollPage};
+use crate::poll::send_retry;
+use crate::poll::stats::{AnswerRename, PollStats};
+use crate::poll::summary;
+use chrono::{DateTime, Duration, Utc};
 use sqlx::PgPool;
-use std::error::Error;
 use uuid::Uuid;
 
 pub struct PollUseCases<'a> {
@@ -16,48 +30,220 @@ impl PollUseCases<'_> {
         }
     }
 
-    pub async fn get_poll_by_id(&self, id: Uuid) -> Result<Poll, Box<dyn Error>> {
+    pub async fn get_poll_by_id(&self, id: Uuid) -> Result<Poll, PollError> {
         let poll = self.poll_repository.find_by_id(id).await?;
         Ok(poll)
     }
 
-    pub async fn get_polls(&self) -> Result<Vec<Poll>, Box<dyn Error>> {
-        let polls = self.poll_repository.get_all().await?;
-        Ok(polls)
+    pub async fn search_polls(&self, filter: PollFilter) -> Result<PollPage, PollError> {
+        let mut polls = self.poll_repository.search(&filter).await?;
+
+        let limit = filter.limit as usize;
+        let next_cursor = if polls.len() > limit {
+            polls.truncate(limit);
+            polls
+                .last()
+                .map(|poll| PollCursor::after(filter.sort, poll))
+        } else {
+            None
+        };
+
+        Ok(PollPage { polls, next_cursor })
     }
 
-    pub async fn get_unsent_polls(&self) -> Result<Vec<Poll>, Box<dyn Error>> {
+    pub async fn get_unsent_polls(&self) -> Result<Vec<Poll>, PollError> {
         let polls = self.poll_repository.get_unsent().await?;
         Ok(polls)
     }
 
-    pub async fn save_poll(&self, poll: Poll) -> Result<Uuid, Box<dyn Error>> {
-        self.poll_repository.save(&poll).await
+    // The renamed answers keep their votes, the other answers replaced are
+    // deleted.
+    pub async fn save_poll(&self, poll: Poll, renames: &[AnswerRename]) -> Result<Uuid, PollError> {
+        let id = self.poll_repository.save(&poll, renames).await?;
+        self.notify_sender(POLL_CHANGES_CHANNEL, id).await;
+        Ok(id)
+    }
+
+    // Claims a scheduled send before posting the poll, so that it is sent once
+    // even with several senders running. The send is counted right away,
+    // retiring the poll once its maximum number of occurrences is reached.
+    pub async fn claim_poll_send(
+        &self,
+        id: Uuid,
+        scheduled_at: DateTime<Utc>,
+        sender: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, PollError> {
+        self.poll_repository
+            .claim_send(id, scheduled_at, sender, at)
+            .await
     }
 
-    pub async fn delete_poll_by_id(&self, id: Uuid) -> Result<(), Box<dyn Error>> {
+    pub async fn complete_poll_send(
+        &self,
+        id: Uuid,
+        scheduled_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Result<(), PollError> {
+        self.poll_repository
+            .complete_send(id, scheduled_at, at)
+            .await
+    }
+
+    // Records the failure of a claimed send and plans its next attempt.
+    // `posted` tells whether some messages were posted before the failure,
+    // the send is then given up as attempting it again would post them twice.
+    // A claim left behind by a sender that crashed while posting is never
+    // attempted again either.
+    pub async fn fail_poll_send(
+        &self,
+        mut failure: PollSendFailure,
+        posted: bool,
+    ) -> Result<PollSendFailure, PollError> {
+        failure.retry_at = if posted {
+            None
+        } else {
+            send_retry::next_attempt_at(failure.attempt, failure.failed_at)
+        };
+
+        self.poll_repository.fail_send(&failure, posted).await?;
+        Ok(failure)
+    }
+
+    pub async fn claim_due_send_retries(
+        &self,
+        sender: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Vec<PollSend>, PollError> {
+        self.poll_repository.claim_due_retries(sender, at).await
+    }
+
+    pub async fn get_next_send_retry_at(&self) -> Result<Option<DateTime<Utc>>, PollError> {
+        self.poll_repository.next_retry_at().await
+    }
+
+    // The failed attempts along with the sends left claimed, last first.
+    pub async fn get_poll_send_failures(
+        &self,
+        id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<PollSendFailure>, PollError> {
+        self.poll_repository.find_by_id(id).await?;
+        let mut failures = self
+            .poll_repository
+            .find_stuck_sends(id, now - Duration::seconds(send_retry::STUCK_CLAIM_SECONDS))
+            .await?;
+        failures.extend(self.poll_repository.find_send_failures(id).await?);
+        failures.sort_by_key(|f| std::cmp::Reverse(f.failed_at));
+
+        Ok(failures)
+    }
+
+    // Queues a send of the poll for the sender, its schedule and sent state
+    // are left untouched.
+    pub async fn request_poll_send(&self, id: Uuid) -> Result<PollSendRequest, PollError> {
+        self.poll_repository.find_by_id(id).await?;
+        let request = self.poll_repository.create_send_request(id).await?;
+        self.notify_sender(SEND_REQUESTS_CHANNEL, id).await;
+        Ok(request)
+    }
+
+    pub async fn claim_send_requests(
+        &self,
+        sender: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(Vec<PollSend>, Vec<PollSendRequest>), PollError> {
+        self.poll_repository.claim_send_requests(sender, at).await
+    }
+
+    pub async fn set_poll_discord_ids(
+        &self,
+        id: Uuid,
+        guild_id: i64,
+        channel_id: i64,
+    ) -> Result<(), PollError> {
+        self.poll_repository
+            .update_discord_ids(id, guild_id, channel_id)
+            .await
+    }
+
+    pub async fn mark_polls_evaluated(
+        &self,
+        ids: &[Uuid],
+        at: DateTime<Utc>,
+    ) -> Result<(), PollError> {
+        self.poll_repository.update_last_evaluated_at(ids, at).await
+    }
+
+    // Pauses the poll until the given date, or until it is resumed.
+    pub async fn pause_poll(
+        &self,
+        id: Uuid,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Poll, PollError> {
+        match until {
+            Some(until) => {
+                self.poll_repository
+                    .update_pause(id, true, Some(until))
+                    .await?
+            }
+            None => self.poll_repository.update_pause(id, false, None).await?,
+        };
+
+        self.notify_sender(POLL_CHANGES_CHANNEL, id).await;
+        self.poll_repository.find_by_id(id).await
+    }
+
+    pub async fn resume_poll(&self, id: Uuid) -> Result<Poll, PollError> {
+        self.poll_repository.update_pause(id, true, None).await?;
+        self.notify_sender(POLL_CHANGES_CHANNEL, id).await;
+        self.poll_repository.find_by_id(id).await
+    }
+
+    pub async fn delete_poll_by_id(&self, id: Uuid) -> Result<(), PollError> {
         self.poll_repository.delete_poll(id).await?;
+        self.notify_sender(POLL_CHANGES_CHANNEL, id).await;
         Ok(())
     }
 
+    // The change is saved already, the sender picks it up on its next reload
+    // when the notification is lost.
+    async fn notify_sender(&self, channel: &str, id: Uuid) {
+        if let Err(e) = self.poll_repository.notify(channel, id).await {
+            eprintln!("Could not notify the sender of {:?}: {:?}", id, e);
+        }
+    }
+
     pub async fn get_poll_instances_by_poll_id(
         &self,
         id: Uuid,
-    ) -> Result<Vec<PollInstance>, Box<dyn Error>> {
+    ) -> Result<Vec<PollInstance>, PollError> {
         let poll = self.poll_repository.find_by_id(id).await?;
         let poll_instance = self.poll_instance_repository.find_by_poll(poll).await?;
         Ok(poll_instance)
     }
 
-    pub async fn get_poll_instance_by_id(&self, id: i64) -> Result<PollInstance, Box<dyn Error>> {
+    pub async fn get_poll_occurrences_by_poll_id(
+        &self,
+        id: Uuid,
+    ) -> Result<Vec<PollOccurrence>, PollError> {
+        let poll = self.poll_repository.find_by_id(id).await?;
+        self.poll_instance_repository
+            .find_occurrences_by_poll(poll)
+            .await
+    }
+
+    pub async fn get_poll_instance_by_id(&self, id: i64) -> Result<PollInstance, PollError> {
         let mut instance = self.poll_instance_repository.find(id).await?;
         let poll = self
             .poll_repository
             .find_by_id(instance.poll_uuid.unwrap())
             .await?;
         let answers = self.get_answers_by_instance_id(id).await?;
+        let votes = self.poll_instance_repository.find_votes(id).await?;
 
         instance.answers = answers;
+        instance.votes = votes;
         instance.poll = Some(poll);
 
         Ok(instance)
@@ -66,7 +252,7 @@ impl PollUseCases<'_> {
     pub async fn get_answers_by_instance_id(
         &self,
         id: i64,
-    ) -> Result<Vec<PollInstanceAnswer>, Box<dyn Error>> {
+    ) -> Result<Vec<PollInstanceAnswer>, PollError> {
         let answers = self.poll_instance_repository.find_answers(id).await?;
         Ok(answers)
     }
@@ -74,7 +260,7 @@ impl PollUseCases<'_> {
     pub async fn get_poll_instance_answers_from_poll_id(
         &self,
         poll_id: Uuid,
-    ) -> Result<Vec<PollInstanceAnswer>, Box<dyn Error>> {
+    ) -> Result<Vec<PollInstanceAnswer>, PollError> {
         let poll_instance_answers = self
             .poll_instance_repository
             .find_answers_by_poll_id(poll_id)
@@ -82,8 +268,321 @@ impl PollUseCases<'_> {
         Ok(poll_instance_answers)
     }
 
-    pub async fn save_instance(&self, instance: PollInstance) -> Result<(), Box<dyn Error>> {
+    // Statistics of the sends within the range, the range is half open.
+    pub async fn get_poll_stats(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<PollStats, PollError> {
+        let from = from.map(|at| at.timestamp());
+        let to = to.map(|at| at.timestamp());
+
+        let occurrences = self
+            .poll_instance_repository
+            .count_occurrences(id, from, to)
+            .await?;
+        let answers = self
+            .poll_instance_repository
+            .find_answer_stats(id, from, to)
+            .await?;
+
+        Ok(PollStats {
+            occurrences,
+            answers,
+        })
+    }
+
+    pub async fn save_instance(&self, instance: PollInstance) -> Result<(), PollError> {
         self.poll_instance_repository.save(instance).await?;
         Ok(())
     }
+
+    pub async fn add_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        self.poll_instance_repository.add_vote(id, vote).await
+    }
+
+    pub async fn remove_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        self.poll_instance_repository.remove_vote(id, vote).await
+    }
+
+    // Corrects the votes of the instance with the ones known by discord.
+    pub async fn reconcile_instance(
+        &self,
+        id: i64,
+        discord: &impl DiscordPolls,
+    ) -> Result<PollInstance, PollError> {
+        let mut instance = self.get_poll_instance_by_id(id).await?;
+        let channel_id = instance_channel_id(&instance)?;
+
+        let votes = fetch_votes(discord, channel_id, &instance).await?;
+        let stored = instance.votes.clone();
+        if instance.reconcile_votes(votes) {
+            println!("[reconcile] votes of poll instance {} corrected", id);
+            self.poll_instance_repository
+                .update_votes(id, &VoteChanges::between(&stored, &instance.votes))
+                .await?;
+        }
+
+        Ok(instance)
+    }
+
+    // Reconciles the open instances and the recently closed ones, returns
+    // how many were reconciled.
+    pub async fn reconcile_recent_instances(
+        &self,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<usize, PollError> {
+        let since = now - Duration::seconds(RECONCILE_AFTER_CLOSE_SECONDS);
+        let ids = self
+            .poll_instance_repository
+            .find_ids_ending_after(since)
+            .await?;
+
+        let mut reconciled = 0;
+        for id in ids {
+            match self.reconcile_instance(id, discord).await {
+                Ok(_) => reconciled += 1,
+                Err(e) => eprintln!("Could not reconcile poll instance {}: {:?}", id, e),
+            }
+        }
+
+        Ok(reconciled)
+    }
+
+    // Stores the final results of an expired instance and closes it. Discord
+    // finalizes the results shortly after the poll ends, until then the
+    // instance is left open. Instances whose results cannot be fetched are
+    // eventually closed with the votes known so far.
+    pub async fn finalize_instance(
+        &self,
+        id: i64,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<PollInstance, PollError> {
+        let mut instance = self.get_poll_instance_by_id(id).await?;
+        if instance.status == PollInstanceStatus::Closed || !instance.is_expired(&now) {
+            return Ok(instance);
+        }
+
+        let give_up = instance
+            .ends_at
+            .is_some_and(|ends_at| ends_at + Duration::seconds(FINALIZE_GIVE_UP_SECONDS) <= now);
+
+        let results = match self.fetch_final_results(&instance, discord).await {
+            Ok(results) => results,
+            Err(e) if give_up => {
+                eprintln!("Could not finalize poll instance {}: {:?}", id, e);
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        match results {
+            Some((results, votes)) => {
+                let stored = instance.votes.clone();
+                instance.reconcile_votes(votes);
+                self.poll_instance_repository
+                    .update_votes(id, &VoteChanges::between(&stored, &instance.votes))
+                    .await?;
+                instance.finalize(&results, now);
+            }
+            None if give_up => instance.close(),
+            None => return Ok(instance),
+        }
+
+        // closed meanwhile by an other task, its results are the stored ones
+        if !self.poll_instance_repository.close(&instance).await? {
+            return self.get_poll_instance_by_id(id).await;
+        }
+
+        Ok(instance)
+    }
+
+    pub async fn finalize_expired_instances(
+        &self,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<usize, PollError> {
+        let ids = self
+            .poll_instance_repository
+            .find_expired_open_ids(now)
+            .await?;
+
+        let mut closed = 0;
+        for id in ids {
+            match self.finalize_instance(id, discord, now).await {
+                Ok(instance) if instance.status == PollInstanceStatus::Closed => closed += 1,
+                Ok(_) => (),
+                Err(e) => eprintln!("Could not finalize poll instance {}: {:?}", id, e),
+            }
+        }
+
+        Ok(closed)
+    }
+
+    // Ends the poll before its expiry, along with the other messages of the
+    // same send: answers are split across several messages and they make a
+    // single poll for the users. Returns the instances that were ended.
+    pub async fn close_instance(
+        &self,
+        id: i64,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<PollInstance>, PollError> {
+        let instance = self.get_poll_instance_by_id(id).await?;
+        let ids = match instance.occurrence_id {
+            Some(occurrence_id) => {
+                self.poll_instance_repository
+                    .find_ids_by_occurrence(occurrence_id)
+                    .await?
+            }
+            None => vec![id],
+        };
+
+        let mut closed: Vec<PollInstance> = Vec::new();
+        for id in ids {
+            let mut instance = self.get_poll_instance_by_id(id).await?;
+            if instance.status == PollInstanceStatus::Closed || instance.is_expired(&now) {
+                continue;
+            }
+
+            let channel_id = instance_channel_id(&instance)?;
+            if end_instance(
+                &self.poll_instance_repository,
+                discord,
+                channel_id,
+                &mut instance,
+                now,
+            )
+            .await?
+            {
+                closed.push(instance);
+            }
+        }
+
+        // a send partly closed by an earlier call is closed entirely
+        if closed.is_empty() {
+            return Err(PollError::Conflict(format!(
+                "poll instance {} has already ended",
+                id
+            )));
+        }
+
+        Ok(closed)
+    }
+
+    // Posts the results summary of every send whose messages are all closed.
+    // Returns how many summaries were posted.
+    pub async fn announce_results(
+        &self,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<usize, PollError> {
+        let since = now - Duration::seconds(ANNOUNCE_AFTER_CLOSE_SECONDS);
+        let sends = self
+            .poll_instance_repository
+            .find_unannounced_sends(since)
+            .await?;
+
+        let mut announced = 0;
+        for (poll_id, occurrence_id) in sends {
+            match self
+                .announce_send(poll_id, occurrence_id, discord, now)
+                .await
+            {
+                Ok(true) => announced += 1,
+                Ok(false) => (),
+                Err(e) => eprintln!(
+                    "Could not announce results of poll {} occurrence {}: {:?}",
+                    poll_id, occurrence_id, e
+                ),
+            }
+        }
+
+        Ok(announced)
+    }
+
+    // The send is marked as announced before the summary is posted, so that
+    // it is posted once. The mark is removed when the summary is not posted.
+    // Returns whether it was posted here.
+    async fn announce_send(
+        &self,
+        poll_id: Uuid,
+        occurrence_id: i64,
+        discord: &impl DiscordPolls,
+        now: DateTime<Utc>,
+    ) -> Result<bool, PollError> {
+        let poll = self.poll_repository.find_by_id(poll_id).await?;
+        let ids = self
+            .poll_instance_repository
+            .find_ids_by_occurrence(occurrence_id)
+            .await?;
+
+        let mut instances: Vec<PollInstance> = Vec::new();
+        for id in &ids {
+            instances.push(self.get_poll_instance_by_id(*id).await?);
+        }
+
+        let channel_id = match poll.results_channel_id {
+            Some(channel_id) => channel_id,
+            None => instance_channel_id(&instances[0])?,
+        };
+
+        let template = poll
+            .results_template
+            .as_deref()
+            .unwrap_or(summary::DEFAULT_TEMPLATE);
+        let message = summary::render(template, &poll.question, &instances);
+
+        if !self
+            .poll_instance_repository
+            .mark_announced(&ids, now)
+            .await?
+        {
+            return Ok(false);
+        }
+
+        if let Err(e) = discord.send_message(channel_id, &message).await {
+            self.poll_instance_repository
+                .unmark_announced(&ids, now)
+                .await?;
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    // The finalized results with the votes they were counted from, none when
+    // discord has not finalized them yet.
+    async fn fetch_final_results(
+        &self,
+        instance: &PollInstance,
+        discord: &impl DiscordPolls,
+    ) -> Result<Option<(PollResults, Vec<PollVote>)>, PollError> {
+        let channel_id = instance_channel_id(instance)?;
+
+        match discord.poll_results(channel_id, instance.id).await? {
+            Some(results) if results.is_finalized => {
+                let votes = fetch_votes(discord, channel_id, instance).await?;
+                Ok(Some((results, votes)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// Instances sent before their channel was recorded were posted in the channel
+// of their poll.
+fn instance_channel_id(instance: &PollInstance) -> Result<i64, PollError> {
+    instance
+        .channel_id
+        .or(instance.poll.as_ref().and_then(|p| p.channel_id))
+        .ok_or_else(|| {
+            PollError::Conflict(format!(
+                "channel of poll instance {} is unknown",
+                instance.id
+            ))
+        })
 }
@@ -0,0 +1,423 @@
+use crate::discord::DiscordPolls;
+use crate::poll::domain::{PollInstance, PollVote, VoteChanges};
+use crate::poll::error::PollError;
+use crate::poll::repository::PollInstanceRepository;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+
+// maximum number of voters discord returns per request
+pub const VOTERS_PAGE_SIZE: u8 = 100;
+// closed polls keep being reconciled for a while, votes can still be
+// received late by the bot
+pub const RECONCILE_AFTER_CLOSE_SECONDS: i64 = 60 * 60;
+// expired instances whose final results cannot be fetched for that long are
+// closed with the votes known so far
+pub const FINALIZE_GIVE_UP_SECONDS: i64 = 60 * 60 * 24;
+// results of sends closed before that are not announced anymore, e.g. when
+// the announcement is enabled on an existing poll
+pub const ANNOUNCE_AFTER_CLOSE_SECONDS: i64 = 60 * 60 * 24;
+
+// Fetches the votes of every answer of the instance.
+pub async fn fetch_votes(
+    discord: &impl DiscordPolls,
+    channel_id: i64,
+    instance: &PollInstance,
+) -> Result<Vec<PollVote>, PollError> {
+    fetch_votes_by_page(discord, channel_id, instance, VOTERS_PAGE_SIZE).await
+}
+
+// Where the ended instances are written to.
+pub trait InstanceStore {
+    fn close(
+        &self,
+        instance: &PollInstance,
+    ) -> impl Future<Output = Result<bool, PollError>> + Send;
+
+    fn update_votes(
+        &self,
+        id: i64,
+        changes: &VoteChanges,
+    ) -> impl Future<Output = Result<(), PollError>> + Send;
+}
+
+impl InstanceStore for PollInstanceRepository<'_> {
+    async fn close(&self, instance: &PollInstance) -> Result<bool, PollError> {
+        PollInstanceRepository::close(self, instance).await
+    }
+
+    async fn update_votes(&self, id: i64, changes: &VoteChanges) -> Result<(), PollError> {
+        PollInstanceRepository::update_votes(self, id, changes).await
+    }
+}
+
+// Ends the poll of the instance and closes the instance right away, discord
+// has ended it whatever fails next. The votes are then corrected, when that
+// fails the reconciliation of the recently closed instances does it later.
+// Returns whether the instance was closed here.
+pub async fn end_instance(
+    store: &impl InstanceStore,
+    discord: &impl DiscordPolls,
+    channel_id: i64,
+    instance: &mut PollInstance,
+    now: DateTime<Utc>,
+) -> Result<bool, PollError> {
+    let results = discord.end_poll(channel_id, instance.id).await?;
+    instance.end_early(results.as_ref(), now);
+    if !store.close(instance).await? {
+        return Ok(false);
+    }
+
+    // the counts stay the ones discord had when the poll was ended
+    let mut reconciled = instance.clone();
+    let result = match fetch_votes(discord, channel_id, instance).await {
+        Ok(votes) => {
+            reconciled.reconcile_votes(votes);
+            store
+                .update_votes(
+                    instance.id,
+                    &VoteChanges::between(&instance.votes, &reconciled.votes),
+                )
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(_) => instance.votes = reconciled.votes,
+        Err(e) => eprintln!(
+            "Could not update the votes of poll instance {}: {:?}",
+            instance.id, e
+        ),
+    }
+
+    Ok(true)
+}
+
+async fn fetch_votes_by_page(
+    discord: &impl DiscordPolls,
+    channel_id: i64,
+    instance: &PollInstance,
+    limit: u8,
+) -> Result<Vec<PollVote>, PollError> {
+    let mut votes: Vec<PollVote> = Vec::new();
+
+    for answer in &instance.answers {
+        let mut after: Option<i64> = None;
+
+        loop {
+            let page = discord
+                .answer_voters(
+                    channel_id,
+                    instance.id,
+                    answer.discord_answer_id,
+                    after,
+                    limit,
+                )
+                .await?;
+
+            votes.extend(page.iter().map(|user_id| PollVote {
+                answer_id: answer.discord_answer_id,
+                user_id: *user_id,
+            }));
+
+            // users are sorted by id, the last one is where the next page starts
+            if page.len() < limit as usize {
+                break;
+            }
+            after = page.last().copied();
+        }
+    }
+
+    Ok(votes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::domain::{
+        Poll, PollAnswerCount, PollInstanceAnswer, PollInstanceStatus, PollResults,
+    };
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    // channel, message, answer and the user the page starts after
+    type Request = (i64, i64, i64, Option<i64>);
+
+    struct MockVoters {
+        voters: HashMap<i64, Vec<i64>>,
+        requests: Mutex<Vec<Request>>,
+        ended: Mutex<Vec<i64>>,
+        failing: bool,
+    }
+
+    impl MockVoters {
+        fn new(voters: HashMap<i64, Vec<i64>>) -> MockVoters {
+            MockVoters {
+                voters,
+                requests: Mutex::new(Vec::new()),
+                ended: Mutex::new(Vec::new()),
+                failing: false,
+            }
+        }
+
+        // the voters cannot be fetched
+        fn failing(mut self) -> MockVoters {
+            self.failing = true;
+            self
+        }
+    }
+
+    // instances closed and votes written, the instances were closed
+    // meanwhile when not open
+    struct MockStore {
+        open: bool,
+        closed: Mutex<Vec<PollInstance>>,
+        changes: Mutex<Vec<(i64, VoteChanges)>>,
+    }
+
+    impl MockStore {
+        fn new(open: bool) -> MockStore {
+            MockStore {
+                open,
+                closed: Mutex::new(Vec::new()),
+                changes: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl InstanceStore for MockStore {
+        async fn close(&self, instance: &PollInstance) -> Result<bool, PollError> {
+            if self.open {
+                self.closed.lock().unwrap().push(instance.clone());
+            }
+            Ok(self.open)
+        }
+
+        async fn update_votes(&self, id: i64, changes: &VoteChanges) -> Result<(), PollError> {
+            self.changes.lock().unwrap().push((
+                id,
+                VoteChanges {
+                    added: changes.added.clone(),
+                    removed: changes.removed.clone(),
+                },
+            ));
+            Ok(())
+        }
+    }
+
+    impl DiscordPolls for MockVoters {
+        async fn answer_voters(
+            &self,
+            channel_id: i64,
+            message_id: i64,
+            answer_id: i64,
+            after: Option<i64>,
+            limit: u8,
+        ) -> Result<Vec<i64>, PollError> {
+            if self.failing {
+                return Err(PollError::from(serenity::Error::Other("unavailable")));
+            }
+
+            self.requests
+                .lock()
+                .unwrap()
+                .push((channel_id, message_id, answer_id, after));
+
+            Ok(self
+                .voters
+                .get(&answer_id)
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|id| after.is_none_or(|after| *id > after))
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn poll_results(
+            &self,
+            _channel_id: i64,
+            _message_id: i64,
+        ) -> Result<Option<PollResults>, PollError> {
+            Ok(None)
+        }
+
+        async fn send_message(&self, _channel_id: i64, _content: &str) -> Result<(), PollError> {
+            Ok(())
+        }
+
+        async fn end_poll(
+            &self,
+            _channel_id: i64,
+            message_id: i64,
+        ) -> Result<Option<PollResults>, PollError> {
+            self.ended.lock().unwrap().push(message_id);
+            Ok(Some(PollResults {
+                is_finalized: false,
+                answer_counts: vec![PollAnswerCount {
+                    answer_id: 2,
+                    count: 3,
+                }],
+            }))
+        }
+    }
+
+    fn create_instance() -> PollInstance {
+        let mut instance = PollInstance::new(Poll::new());
+        instance.id = 10;
+        instance.answers = vec![
+            PollInstanceAnswer {
+                discord_answer_id: 1,
+                answer: String::from("one"),
+                votes: 0,
+            },
+            PollInstanceAnswer {
+                discord_answer_id: 2,
+                answer: String::from("two"),
+                votes: 0,
+            },
+        ];
+        instance
+    }
+
+    #[tokio::test]
+    async fn test_fetch_votes() {
+        let voters = MockVoters::new(HashMap::from([(1, vec![100, 101]), (2, vec![100])]));
+        let instance = create_instance();
+
+        let votes = fetch_votes(&voters, 5, &instance).await.unwrap();
+
+        assert_eq!(
+            vec![
+                PollVote {
+                    answer_id: 1,
+                    user_id: 100
+                },
+                PollVote {
+                    answer_id: 1,
+                    user_id: 101
+                },
+                PollVote {
+                    answer_id: 2,
+                    user_id: 100
+                },
+            ],
+            votes
+        );
+        assert_eq!(
+            vec![(5, 10, 1, None), (5, 10, 2, None)],
+            *voters.requests.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_votes_pages() {
+        let voters = MockVoters::new(HashMap::from([(1, vec![100, 101, 102, 103, 104])]));
+        let instance = create_instance();
+
+        let votes = fetch_votes_by_page(&voters, 5, &instance, 2).await.unwrap();
+
+        assert_eq!(5, votes.len());
+        assert_eq!(
+            vec![
+                (5, 10, 1, None),
+                (5, 10, 1, Some(101)),
+                (5, 10, 1, Some(103)),
+                (5, 10, 2, None)
+            ],
+            *voters.requests.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_missed_votes() {
+        let voters = MockVoters::new(HashMap::from([(2, vec![100, 101])]));
+        let mut instance = create_instance();
+        instance.add_vote(1, 100).unwrap();
+
+        let votes = fetch_votes(&voters, 5, &instance).await.unwrap();
+
+        assert!(instance.reconcile_votes(votes));
+        assert_eq!(0, instance.answers[0].votes);
+        assert_eq!(2, instance.answers[1].votes);
+    }
+
+    #[tokio::test]
+    async fn test_end_instance() {
+        let voters = MockVoters::new(HashMap::from([(2, vec![100, 101])]));
+        let store = MockStore::new(true);
+        let now = Utc::now();
+        let mut instance = create_instance();
+        instance.add_vote(1, 100).unwrap();
+
+        assert!(end_instance(&store, &voters, 5, &mut instance, now)
+            .await
+            .unwrap());
+
+        assert_eq!(vec![10], *voters.ended.lock().unwrap());
+        assert_eq!(PollInstanceStatus::Closed, instance.status);
+        assert_eq!(Some(now), instance.ends_at);
+        assert_eq!(3, instance.answers[1].votes);
+        assert_eq!(2, instance.votes.len());
+
+        let closed = store.closed.lock().unwrap();
+        assert_eq!(1, closed.len());
+        assert_eq!(PollInstanceStatus::Closed, closed[0].status);
+        assert_eq!(Some(now), closed[0].finalized_at);
+        assert_eq!(
+            vec![(
+                10,
+                VoteChanges {
+                    added: vec![
+                        PollVote {
+                            answer_id: 2,
+                            user_id: 100
+                        },
+                        PollVote {
+                            answer_id: 2,
+                            user_id: 101
+                        },
+                    ],
+                    removed: vec![PollVote {
+                        answer_id: 1,
+                        user_id: 100
+                    }],
+                }
+            )],
+            *store.changes.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_end_instance_votes_failing() {
+        let voters = MockVoters::new(HashMap::new()).failing();
+        let store = MockStore::new(true);
+        let now = Utc::now();
+        let mut instance = create_instance();
+        instance.add_vote(1, 100).unwrap();
+
+        // the instance is closed even though its votes could not be fetched
+        assert!(end_instance(&store, &voters, 5, &mut instance, now)
+            .await
+            .unwrap());
+
+        assert_eq!(1, store.closed.lock().unwrap().len());
+        assert_eq!(PollInstanceStatus::Closed, instance.status);
+        assert_eq!(1, instance.votes.len());
+        assert!(store.changes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_end_instance_closed_meanwhile() {
+        let voters = MockVoters::new(HashMap::from([(2, vec![100])]));
+        let store = MockStore::new(false);
+        let mut instance = create_instance();
+
+        assert!(!end_instance(&store, &voters, 5, &mut instance, Utc::now())
+            .await
+            .unwrap());
+
+        assert!(voters.requests.lock().unwrap().is_empty());
+        assert!(store.changes.lock().unwrap().is_empty());
+    }
+}
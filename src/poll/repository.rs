@@ -1,8 +1,14 @@
-use crate::poll::domain::{Poll, PollInstance, PollInstanceAnswer};
+use crate::poll::domain::{
+    Poll, PollInstance, PollInstanceAnswer, PollInstanceStatus, PollOccurrence, PollSend,
+    PollSendFailure, PollSendRequest, PollSendStatus, PollVote, VoteChanges,
+};
+use crate::poll::error::PollError;
+use crate::poll::search::{contains_pattern, PollFilter, SortKey};
+use crate::poll::stats::{answer_changes, AnswerRename, AnswerStats};
+use chrono::{DateTime, Utc};
 use futures::TryStreamExt;
-use sqlx::postgres::PgPool;
-use sqlx::Row;
-use std::error::Error;
+use sqlx::postgres::{PgPool, PgRow, Postgres};
+use sqlx::{QueryBuilder, Row, Transaction};
 use uuid::Uuid;
 
 pub struct PollRepository<'a> {
@@ -15,11 +21,95 @@ pub struct PollInstanceRepository<'a> {
 
 #[derive(sqlx::FromRow)]
 pub struct AnswerRow {
+    pub id: i32,
     pub answer: String,
 }
 
+fn poll_from_row(row: &PgRow, id: Uuid, answers: Vec<String>) -> Result<Poll, PollError> {
+    let misfire_policy: String = row.try_get("misfire_policy")?;
+
+    Ok(Poll {
+        id,
+        cron: row.try_get("cron")?,
+        question: row.try_get("question")?,
+        multiselect: row.try_get("multiselect")?,
+        guild: row.try_get("guild")?,
+        channel: row.try_get("channel")?,
+        guild_id: row.try_get("guild_id")?,
+        channel_id: row.try_get("channel_id")?,
+        answers,
+        duration: row.try_get("duration")?,
+        onetime: row.try_get("onetime")?,
+        sent: row.try_get("sent")?,
+        timezone: row.try_get("timezone")?,
+        misfire_policy: misfire_policy.parse().map_err(PollError::decode)?,
+        last_evaluated_at: row.try_get("last_evaluated_at")?,
+        enabled: row.try_get("enabled")?,
+        paused_until: row.try_get("paused_until")?,
+        start_at: row.try_get("start_at")?,
+        end_at: row.try_get("end_at")?,
+        max_occurrences: row.try_get("max_occurrences")?,
+        occurrences_sent: row.try_get("occurrences_sent")?,
+        expose_voters: row.try_get("expose_voters")?,
+        announce_results: row.try_get("announce_results")?,
+        results_template: row.try_get("results_template")?,
+        results_channel_id: row.try_get("results_channel_id")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn instance_from_row(row: &PgRow) -> Result<PollInstance, PollError> {
+    let poll_id: String = row.try_get("poll_id")?;
+    let poll_uuid = Uuid::parse_str(poll_id.as_str()).map_err(PollError::decode)?;
+    let status: String = row.try_get("status")?;
+
+    Ok(PollInstance {
+        id: row.try_get("id")?,
+        sent_at: row.try_get("sent_at")?,
+        answers: Vec::new(),
+        votes: Vec::new(),
+        channel_id: row.try_get("channel_id")?,
+        ends_at: row.try_get("ends_at")?,
+        status: status.parse().map_err(PollError::decode)?,
+        finalized_at: row.try_get("finalized_at")?,
+        announced_at: row.try_get("announced_at")?,
+        occurrence_id: row.try_get("occurrence_id")?,
+        scheduled_at: None,
+        manual: row.try_get("manual")?,
+        poll_uuid: Some(poll_uuid),
+        poll: None,
+    })
+}
+
+fn sends_from_rows(rows: Vec<PgRow>) -> Result<Vec<PollSend>, PollError> {
+    let mut sends: Vec<PollSend> = Vec::new();
+    for row in rows {
+        let poll_id: String = row.try_get("poll_id")?;
+        sends.push(PollSend {
+            poll_id: Uuid::parse_str(poll_id.as_str()).map_err(PollError::decode)?,
+            scheduled_at: row.try_get("scheduled_at")?,
+            attempts: row.try_get("attempts")?,
+            manual: row.try_get("manual")?,
+        });
+    }
+    sends.sort_by_key(|s| s.scheduled_at);
+
+    Ok(sends)
+}
+
+fn send_request_from_row(row: &PgRow) -> Result<PollSendRequest, PollError> {
+    let poll_id: String = row.try_get("poll_id")?;
+
+    Ok(PollSendRequest {
+        id: row.try_get("id")?,
+        poll_id: Uuid::parse_str(poll_id.as_str()).map_err(PollError::decode)?,
+        requested_at: row.try_get("requested_at")?,
+        processed_at: row.try_get("processed_at")?,
+    })
+}
+
 impl<'a> PollRepository<'a> {
-    pub async fn save(&self, p: &Poll) -> Result<Uuid, Box<dyn Error>> {
+    pub async fn save(&self, p: &Poll, renames: &[AnswerRename]) -> Result<Uuid, PollError> {
         let exists = self.poll_exists(p.id).await?;
 
         if !exists {
@@ -28,23 +118,25 @@ impl<'a> PollRepository<'a> {
             self.update_poll(&p).await?;
 
             let saved_answers = self.find_answers(p.id).await?;
-            for answer in &p.answers {
-                match saved_answers.iter().find(|item| item.answer == *answer) {
-                    Some(_) => (),
-                    None => self.create_answer(answer.clone(), p.id).await?,
-                };
+            let saved: Vec<String> = saved_answers.iter().map(|a| a.answer.clone()).collect();
+            let changes = answer_changes(&saved, &p.answers, renames);
+
+            for (i, answer) in changes.renamed {
+                self.rename_answer(&saved_answers[i], answer).await?;
+            }
+            for i in changes.deleted {
+                self.delete_answer(saved_answers[i].answer.clone(), p.id)
+                    .await?;
             }
-            for answer in &saved_answers {
-                if let None = p.answers.iter().find(|item| **item == answer.answer) {
-                    self.delete_answer(answer.answer.clone(), p.id).await?;
-                }
+            for answer in changes.created {
+                self.create_answer(answer, p.id).await?;
             }
         }
 
         Ok(p.id)
     }
 
-    async fn create(&self, p: &Poll) -> Result<(), Box<dyn Error>> {
+    async fn create(&self, p: &Poll) -> Result<(), PollError> {
         match self.create_poll(&p).await {
             Ok(_) => (),
             Err(e) => {
@@ -60,12 +152,13 @@ impl<'a> PollRepository<'a> {
         Ok(())
     }
 
-    async fn create_poll(&self, p: &Poll) -> Result<(), Box<dyn Error>> {
+    async fn create_poll(&self, p: &Poll) -> Result<(), PollError> {
         sqlx::query(
             "
 INSERT INTO polls
-(id, cron, question, multiselect, guild, channel, duration, onetime, sent)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+(id, cron, question, multiselect, guild, channel, duration, onetime, sent, timezone, misfire_policy, enabled, paused_until, start_at, end_at, max_occurrences, guild_id, channel_id, expose_voters,
+    announce_results, results_template, results_channel_id, created_at, last_evaluated_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, NOW())",
         )
         .bind(p.id.to_string())
         .bind(p.cron.clone())
@@ -76,13 +169,27 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         .bind(p.duration)
         .bind(p.onetime.clone())
         .bind(p.sent.clone())
+        .bind(p.timezone.clone())
+        .bind(p.misfire_policy.to_string())
+        .bind(p.enabled)
+        .bind(p.paused_until)
+        .bind(p.start_at)
+        .bind(p.end_at)
+        .bind(p.max_occurrences)
+        .bind(p.guild_id)
+        .bind(p.channel_id)
+        .bind(p.expose_voters)
+        .bind(p.announce_results)
+        .bind(p.results_template.clone())
+        .bind(p.results_channel_id)
+        .bind(p.created_at)
         .execute(self.pool)
         .await?;
 
         Ok(())
     }
 
-    async fn create_answer(&self, a: String, poll_id: Uuid) -> Result<(), Box<dyn Error>> {
+    async fn create_answer(&self, a: String, poll_id: Uuid) -> Result<(), PollError> {
         sqlx::query("INSERT INTO answers (answer, poll_id) VALUES ($1, $2)")
             .bind(a.clone())
             .bind(poll_id.to_string())
@@ -91,7 +198,7 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         Ok(())
     }
 
-    async fn poll_exists(&self, id: Uuid) -> Result<bool, Box<dyn Error>> {
+    async fn poll_exists(&self, id: Uuid) -> Result<bool, PollError> {
         let result = sqlx::query("SELECT id FROM polls WHERE id = $1 LIMIT 1")
             .bind(id.to_string())
             .fetch_optional(self.pool)
@@ -100,21 +207,23 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         Ok(result.is_some())
     }
 
-    async fn find_answers(&self, id: Uuid) -> Result<Vec<AnswerRow>, Box<dyn Error>> {
+    async fn find_answers(&self, id: Uuid) -> Result<Vec<AnswerRow>, PollError> {
         let answers: Vec<AnswerRow> =
-            sqlx::query_as("SELECT answer FROM answers WHERE poll_id = $1")
+            sqlx::query_as("SELECT id, answer FROM answers WHERE poll_id = $1 ORDER BY id")
                 .bind(id.to_string())
                 .fetch_all(self.pool)
                 .await?;
         Ok(answers)
     }
 
-    async fn update_poll(&self, p: &Poll) -> Result<(), Box<dyn Error>> {
+    async fn update_poll(&self, p: &Poll) -> Result<(), PollError> {
         sqlx::query(
             "
 UPDATE polls
-SET cron = $1, question = $2, multiselect = $3, guild = $4, channel = $5, duration = $6, onetime = $7, sent = $8
-WHERE id = $9",
+SET cron = $1, question = $2, multiselect = $3, guild = $4, channel = $5, duration = $6, onetime = $7, timezone = $8, misfire_policy = $9,
+    start_at = $10, end_at = $11, max_occurrences = $12, guild_id = $13, channel_id = $14,
+    expose_voters = $15, announce_results = $16, results_template = $17, results_channel_id = $18
+WHERE id = $19",
         )
         .bind(p.cron.clone())
         .bind(p.question.clone())
@@ -123,7 +232,17 @@ WHERE id = $9",
         .bind(p.channel.clone())
         .bind(p.duration)
         .bind(p.onetime.clone())
-        .bind(p.sent.clone())
+        .bind(p.timezone.clone())
+        .bind(p.misfire_policy.to_string())
+        .bind(p.start_at)
+        .bind(p.end_at)
+        .bind(p.max_occurrences)
+        .bind(p.guild_id)
+        .bind(p.channel_id)
+        .bind(p.expose_voters)
+        .bind(p.announce_results)
+        .bind(p.results_template.clone())
+        .bind(p.results_channel_id)
         .bind(p.id.to_string())
         .execute(self.pool)
         .await?;
@@ -131,98 +250,522 @@ WHERE id = $9",
         Ok(())
     }
 
-    pub async fn find_by_id(&self, id: Uuid) -> Result<Poll, Box<dyn Error>> {
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Poll, PollError> {
         let row = sqlx::query("SELECT * FROM polls WHERE id = $1 LIMIT 1")
             .bind(id.to_string())
-            .fetch_one(self.pool)
-            .await?;
+            .fetch_optional(self.pool)
+            .await?
+            .ok_or_else(|| PollError::not_found("poll", id))?;
 
         let answers = self.find_answers(id).await?;
 
-        Ok(Poll {
+        poll_from_row(
+            &row,
             id,
-            cron: row.try_get(1)?,
-            question: row.try_get(2)?,
-            multiselect: row.try_get(3)?,
-            guild: row.try_get(4)?,
-            channel: row.try_get(5)?,
-            answers: answers.iter().map(|item| item.answer.clone()).collect(),
-            duration: row.try_get(6)?,
-            onetime: row.try_get(7)?,
-            sent: row.try_get(8)?,
-        })
-    }
-
-    pub async fn get_all(&self) -> Result<Vec<Poll>, Box<dyn Error>> {
-        let mut polls: Vec<Poll> = Vec::new();
+            answers.iter().map(|item| item.answer.clone()).collect(),
+        )
+    }
+
+    // Polls matching the filter with their answers, in a single query. One
+    // poll more than the limit is fetched, telling whether a next page exists.
+    pub async fn search(&self, filter: &PollFilter) -> Result<Vec<Poll>, PollError> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "
+SELECT p.*, COALESCE(array_agg(a.answer ORDER BY a.id) FILTER (WHERE a.id IS NOT NULL), '{}') AS answer_list
+FROM polls p
+LEFT JOIN answers a ON a.poll_id = p.id
+WHERE TRUE",
+        );
+
+        if let Some(guild) = &filter.guild {
+            query
+                .push(" AND (p.guild = ")
+                .push_bind(guild.clone())
+                .push(" OR p.guild_id::TEXT = ")
+                .push_bind(guild.clone())
+                .push(")");
+        }
+        if let Some(channel) = &filter.channel {
+            query
+                .push(" AND (p.channel = ")
+                .push_bind(channel.clone())
+                .push(" OR p.channel_id::TEXT = ")
+                .push_bind(channel.clone())
+                .push(")");
+        }
+        if let Some(onetime) = filter.onetime {
+            query.push(" AND p.onetime = ").push_bind(onetime);
+        }
+        if let Some(sent) = filter.sent {
+            query.push(" AND p.sent = ").push_bind(sent);
+        }
+        if let Some(question) = &filter.question {
+            query
+                .push(" AND p.question ILIKE ")
+                .push_bind(contains_pattern(question));
+        }
+        if let Some(guild_ids) = &filter.guild_ids {
+            query
+                .push(" AND p.guild_id = ANY(")
+                .push_bind(guild_ids.clone())
+                .push(")");
+        }
 
-        let mut rows = sqlx::query("SELECT * FROM polls").fetch(self.pool);
+        let column = filter.sort.column();
+        let (order, after) = if filter.sort.is_descending() {
+            ("DESC", "<")
+        } else {
+            ("ASC", ">")
+        };
+
+        if let Some(cursor) = &filter.after {
+            query.push(format!(" AND (p.{}, p.id) {} (", column, after));
+            match &cursor.key {
+                SortKey::CreatedAt(at) => query.push_bind(*at),
+                SortKey::Question(question) => query.push_bind(question.clone()),
+            };
+            query.push(", ").push_bind(cursor.id.to_string()).push(")");
+        }
+
+        query
+            .push(format!(
+                " GROUP BY p.id ORDER BY p.{} {}, p.id {} LIMIT ",
+                column, order, order
+            ))
+            .push_bind(filter.limit + 1);
+
+        let mut polls: Vec<Poll> = Vec::new();
+        let mut rows = query.build().fetch(self.pool);
 
         while let Some(row) = rows.try_next().await? {
-            let id: String = row.try_get(0)?;
-            let parsed_uuid = Uuid::parse_str(id.as_str())?;
-
-            let answers = self
-                .find_answers(parsed_uuid)
-                .await?
-                .iter()
-                .map(|item| item.answer.clone())
-                .collect();
-
-            polls.push(Poll {
-                id: parsed_uuid,
-                cron: row.try_get(1)?,
-                question: row.try_get(2)?,
-                multiselect: row.try_get(3)?,
-                guild: row.try_get(4)?,
-                channel: row.try_get(5)?,
-                answers,
-                duration: row.try_get(6)?,
-                onetime: row.try_get(7)?,
-                sent: row.try_get(8)?,
-            });
+            let id: String = row.try_get("id")?;
+            let parsed_uuid = Uuid::parse_str(id.as_str()).map_err(PollError::decode)?;
+            let answers: Vec<String> = row.try_get("answer_list")?;
+
+            polls.push(poll_from_row(&row, parsed_uuid, answers)?);
         }
 
         Ok(polls)
     }
 
-    pub async fn get_unsent(&self) -> Result<Vec<Poll>, Box<dyn Error>> {
+    // Polls that may still be sent, with their answers in a single query.
+    // Paused ones are included, the schedule resumes them once their pause
+    // ends.
+    pub async fn get_unsent(&self) -> Result<Vec<Poll>, PollError> {
         let mut polls: Vec<Poll> = Vec::new();
 
-        let mut rows = sqlx::query("SELECT * FROM polls WHERE sent = FALSE").fetch(self.pool);
+        let mut rows = sqlx::query(
+            "
+SELECT p.*, COALESCE(array_agg(a.answer ORDER BY a.id) FILTER (WHERE a.id IS NOT NULL), '{}') AS answer_list
+FROM polls p
+LEFT JOIN answers a ON a.poll_id = p.id
+WHERE NOT (p.onetime AND p.sent)
+AND (p.max_occurrences IS NULL OR p.occurrences_sent < p.max_occurrences)
+AND p.enabled = TRUE
+GROUP BY p.id",
+        )
+        .fetch(self.pool);
 
         while let Some(row) = rows.try_next().await? {
-            let id: String = row.try_get(0)?;
-            let parsed_uuid = Uuid::parse_str(id.as_str())?;
-
-            let answers = self
-                .find_answers(parsed_uuid)
-                .await?
-                .iter()
-                .map(|item| item.answer.clone())
-                .collect();
-
-            polls.push(Poll {
-                id: parsed_uuid,
-                cron: row.try_get(1)?,
-                question: row.try_get(2)?,
-                multiselect: row.try_get(3)?,
-                guild: row.try_get(4)?,
-                channel: row.try_get(5)?,
-                answers,
-                duration: row.try_get(6)?,
-                onetime: row.try_get(7)?,
-                sent: row.try_get(8)?,
-            });
+            let id: String = row.try_get("id")?;
+            let parsed_uuid = Uuid::parse_str(id.as_str()).map_err(PollError::decode)?;
+            let answers: Vec<String> = row.try_get("answer_list")?;
+
+            polls.push(poll_from_row(&row, parsed_uuid, answers)?);
         }
 
         Ok(polls)
     }
 
-    pub async fn delete_poll(&self, id: Uuid) -> Result<(), Box<dyn Error>> {
+    // Wakes the sender up, see the notifications module.
+    pub async fn notify(&self, channel: &str, id: Uuid) -> Result<(), PollError> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(channel)
+            .bind(id.to_string())
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_last_evaluated_at(
+        &self,
+        ids: &[Uuid],
+        at: DateTime<Utc>,
+    ) -> Result<(), PollError> {
+        let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+
+        sqlx::query("UPDATE polls SET last_evaluated_at = $1 WHERE id = ANY($2)")
+            .bind(at)
+            .bind(ids)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Claims the scheduled send of the poll, counting it as sent. Returns
+    // false when it was already claimed, by another sender or before a
+    // restart, or when the poll cannot be sent anymore.
+    pub async fn claim_send(
+        &self,
+        id: Uuid,
+        scheduled_at: DateTime<Utc>,
+        claimed_by: &str,
+        at: DateTime<Utc>,
+    ) -> Result<bool, PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        let claimed = sqlx::query(
+            "
+INSERT INTO poll_sends (poll_id, scheduled_at, status, claimed_by, claimed_at)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT DO NOTHING",
+        )
+        .bind(id.to_string())
+        .bind(scheduled_at)
+        .bind(PollSendStatus::Claimed.to_string())
+        .bind(claimed_by)
+        .bind(at)
+        .execute(&mut *tx)
+        .await?;
+
+        if claimed.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        // the row lock serializes the senders on the poll, the counters are
+        // checked again as the ones the caller read may be stale
+        let counted = sqlx::query(
+            "
+UPDATE polls
+SET sent = TRUE, occurrences_sent = occurrences_sent + 1
+WHERE id = $1
+AND NOT (onetime AND sent)
+AND (max_occurrences IS NULL OR occurrences_sent < max_occurrences)",
+        )
+        .bind(id.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        if counted.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    pub async fn complete_send(
+        &self,
+        id: Uuid,
+        scheduled_at: DateTime<Utc>,
+        at: DateTime<Utc>,
+    ) -> Result<(), PollError> {
+        sqlx::query(
+            "UPDATE poll_sends SET status = $1, sent_at = $2 WHERE poll_id = $3 AND scheduled_at = $4",
+        )
+        .bind(PollSendStatus::Sent.to_string())
+        .bind(at)
+        .bind(id.to_string())
+        .bind(scheduled_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Records the failure of the claimed send, which is attempted again at
+    // the failure retry date. Without one the send is given up, and no longer
+    // counted unless some of its messages were posted.
+    pub async fn fail_send(
+        &self,
+        failure: &PollSendFailure,
+        posted: bool,
+    ) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "
+INSERT INTO send_failures (poll_id, scheduled_at, attempt, error, discord_code, http_status, failed_at, retry_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(failure.poll_id.to_string())
+        .bind(failure.scheduled_at)
+        .bind(failure.attempt)
+        .bind(failure.error.clone())
+        .bind(failure.discord_code)
+        .bind(failure.http_status)
+        .bind(failure.failed_at)
+        .bind(failure.retry_at)
+        .execute(&mut *tx)
+        .await?;
+
+        let status = match failure.retry_at {
+            Some(_) => PollSendStatus::Retrying,
+            None => PollSendStatus::Failed,
+        };
+        let send = sqlx::query(
+            "
+UPDATE poll_sends
+SET status = $1, next_attempt_at = $2
+WHERE poll_id = $3 AND scheduled_at = $4
+RETURNING manual",
+        )
+        .bind(status.to_string())
+        .bind(failure.retry_at)
+        .bind(failure.poll_id.to_string())
+        .bind(failure.scheduled_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        // requested sends are not counted
+        let manual = match send {
+            Some(send) => send.try_get("manual")?,
+            None => false,
+        };
+
+        if failure.retry_at.is_none() && !posted && !manual {
+            sqlx::query(
+                "
+UPDATE polls
+SET occurrences_sent = occurrences_sent - 1, sent = occurrences_sent > 1
+WHERE id = $1",
+            )
+            .bind(failure.poll_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Claims the sends whose retry is due, skipping the ones claimed by
+    // another sender.
+    pub async fn claim_due_retries(
+        &self,
+        claimed_by: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Vec<PollSend>, PollError> {
+        let rows = sqlx::query(
+            "
+UPDATE poll_sends
+SET status = $1, claimed_by = $2, claimed_at = $3, attempts = attempts + 1, next_attempt_at = NULL
+WHERE (poll_id, scheduled_at) IN (
+    SELECT poll_id, scheduled_at
+    FROM poll_sends
+    WHERE status = $4 AND next_attempt_at <= $3
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING poll_id, scheduled_at, attempts, manual",
+        )
+        .bind(PollSendStatus::Claimed.to_string())
+        .bind(claimed_by)
+        .bind(at)
+        .bind(PollSendStatus::Retrying.to_string())
+        .fetch_all(self.pool)
+        .await?;
+
+        sends_from_rows(rows)
+    }
+
+    pub async fn next_retry_at(&self) -> Result<Option<DateTime<Utc>>, PollError> {
+        let row = sqlx::query(
+            "SELECT MIN(next_attempt_at) AS next_attempt_at FROM poll_sends WHERE status = $1",
+        )
+        .bind(PollSendStatus::Retrying.to_string())
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(row.try_get("next_attempt_at")?)
+    }
+
+    pub async fn find_send_failures(&self, id: Uuid) -> Result<Vec<PollSendFailure>, PollError> {
+        let rows = sqlx::query(
+            "SELECT * FROM send_failures WHERE poll_id = $1 ORDER BY failed_at DESC, id DESC",
+        )
+        .bind(id.to_string())
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut failures: Vec<PollSendFailure> = Vec::new();
+        for row in rows {
+            failures.push(PollSendFailure {
+                id: row.try_get("id")?,
+                poll_id: id,
+                scheduled_at: row.try_get("scheduled_at")?,
+                attempt: row.try_get("attempt")?,
+                error: row.try_get("error")?,
+                discord_code: row.try_get("discord_code")?,
+                http_status: row.try_get("http_status")?,
+                failed_at: row.try_get("failed_at")?,
+                retry_at: row.try_get("retry_at")?,
+            });
+        }
+
+        Ok(failures)
+    }
+
+    // Sends claimed before the given date and never completed, as failures
+    // that are not retried.
+    pub async fn find_stuck_sends(
+        &self,
+        id: Uuid,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<PollSendFailure>, PollError> {
+        let rows = sqlx::query(
+            "
+SELECT scheduled_at, attempts, claimed_by, claimed_at
+FROM poll_sends
+WHERE poll_id = $1 AND status = $2 AND claimed_at < $3",
+        )
+        .bind(id.to_string())
+        .bind(PollSendStatus::Claimed.to_string())
+        .bind(before)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut failures: Vec<PollSendFailure> = Vec::new();
+        for row in rows {
+            let claimed_by: String = row.try_get("claimed_by")?;
+            failures.push(PollSendFailure {
+                id: None,
+                poll_id: id,
+                scheduled_at: row.try_get("scheduled_at")?,
+                attempt: row.try_get("attempts")?,
+                error: format!("claimed by {} and never completed", claimed_by),
+                discord_code: None,
+                http_status: None,
+                failed_at: row.try_get("claimed_at")?,
+                retry_at: None,
+            });
+        }
+
+        Ok(failures)
+    }
+
+    pub async fn create_send_request(&self, id: Uuid) -> Result<PollSendRequest, PollError> {
+        let row = sqlx::query("INSERT INTO poll_send_requests (poll_id) VALUES ($1) RETURNING *")
+            .bind(id.to_string())
+            .fetch_one(self.pool)
+            .await?;
+
+        send_request_from_row(&row)
+    }
+
+    // Marks the pending requests as processed and claims a send for each of
+    // them in the ledger, where they are recorded and retried like the
+    // scheduled ones. Requests claimed by another sender are skipped so that
+    // each is sent once. The requests whose send was already in the ledger,
+    // such as two requests made at the same time, are returned as dropped.
+    pub async fn claim_send_requests(
+        &self,
+        claimed_by: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(Vec<PollSend>, Vec<PollSendRequest>), PollError> {
+        let rows = sqlx::query(
+            "
+WITH requests AS (
+    UPDATE poll_send_requests
+    SET processed_at = $1
+    WHERE id IN (
+        SELECT id
+        FROM poll_send_requests
+        WHERE processed_at IS NULL
+        ORDER BY requested_at
+        FOR UPDATE SKIP LOCKED
+    )
+    RETURNING id, poll_id, requested_at, processed_at
+),
+firsts AS (
+    SELECT DISTINCT ON (poll_id, requested_at) id, poll_id, requested_at
+    FROM requests
+    ORDER BY poll_id, requested_at, id
+),
+claimed AS (
+    INSERT INTO poll_sends (poll_id, scheduled_at, status, claimed_by, claimed_at, manual)
+    SELECT poll_id, requested_at, $2, $3, $1, TRUE
+    FROM firsts
+    ON CONFLICT DO NOTHING
+    RETURNING poll_id, scheduled_at, attempts, manual
+)
+SELECT r.id, r.poll_id, r.requested_at, r.processed_at, r.requested_at AS scheduled_at,
+    c.attempts, c.manual, c.poll_id IS NOT NULL AND r.id IN (SELECT id FROM firsts) AS claimed
+FROM requests r
+LEFT JOIN claimed c ON c.poll_id = r.poll_id AND c.scheduled_at = r.requested_at",
+        )
+        .bind(at)
+        .bind(PollSendStatus::Claimed.to_string())
+        .bind(claimed_by)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut claimed = Vec::new();
+        let mut dropped = Vec::new();
+        for row in rows {
+            if row.try_get("claimed")? {
+                claimed.push(row);
+            } else {
+                dropped.push(send_request_from_row(&row)?);
+            }
+        }
+        dropped.sort_by_key(|r| r.requested_at);
+
+        Ok((sends_from_rows(claimed)?, dropped))
+    }
+
+    pub async fn update_discord_ids(
+        &self,
+        id: Uuid,
+        guild_id: i64,
+        channel_id: i64,
+    ) -> Result<(), PollError> {
+        sqlx::query("UPDATE polls SET guild_id = $1, channel_id = $2 WHERE id = $3")
+            .bind(guild_id)
+            .bind(channel_id)
+            .bind(id.to_string())
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // The pause state is only changed through here, saving a poll leaves it
+    // untouched. Resuming moves the evaluation time forward so that the
+    // occurrences skipped while paused are not caught up.
+    pub async fn update_pause(
+        &self,
+        id: Uuid,
+        enabled: bool,
+        paused_until: Option<DateTime<Utc>>,
+    ) -> Result<(), PollError> {
+        let result = sqlx::query(
+            "
+UPDATE polls
+SET enabled = $1, paused_until = $2,
+    last_evaluated_at = CASE WHEN $1 AND $2 IS NULL THEN NOW() ELSE last_evaluated_at END
+WHERE id = $3",
+        )
+        .bind(enabled)
+        .bind(paused_until)
+        .bind(id.to_string())
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(PollError::not_found("poll", id));
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_poll(&self, id: Uuid) -> Result<(), PollError> {
         let found = self.poll_exists(id).await?;
         if !found {
-            return Err("not found")?;
+            return Err(PollError::not_found("poll", id));
         }
 
         sqlx::query("DELETE FROM polls WHERE id = $1")
@@ -233,7 +776,30 @@ WHERE id = $9",
         Ok(())
     }
 
-    async fn delete_answer(&self, answer: String, id: Uuid) -> Result<(), Box<dyn Error>> {
+    // The previous name is kept, its votes are counted for the answer.
+    async fn rename_answer(&self, saved: &AnswerRow, answer: String) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE answers SET answer = $1 WHERE id = $2")
+            .bind(answer)
+            .bind(saved.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO answer_renames (answer_id, previous_answer, renamed_at) VALUES ($1, $2, NOW())",
+        )
+        .bind(saved.id)
+        .bind(saved.answer.clone())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_answer(&self, answer: String, id: Uuid) -> Result<(), PollError> {
         sqlx::query("DELETE FROM answers WHERE poll_id = $1 AND answer = $2")
             .bind(id.to_string())
             .bind(answer.clone())
@@ -245,53 +811,38 @@ WHERE id = $9",
 }
 
 impl<'a> PollInstanceRepository<'a> {
-    pub async fn save(&self, i: PollInstance) -> Result<(), Box<dyn Error>> {
+    // Instances are created once, their votes and status then only change
+    // through the targeted writes below.
+    pub async fn save(&self, i: PollInstance) -> Result<(), PollError> {
         let exists = self.exists(i.id).await;
 
         if !exists {
             self.create(&i).await?;
-        } else {
-            self.update_votes(&i).await?;
         }
 
         Ok(())
     }
 
-    pub async fn find(&self, id: i64) -> Result<PollInstance, Box<dyn Error>> {
+    pub async fn find(&self, id: i64) -> Result<PollInstance, PollError> {
         let row = sqlx::query("SELECT * FROM poll_instances WHERE id = $1")
             .bind(id)
-            .fetch_one(self.pool)
-            .await?;
-
-        let poll_id: String = row.try_get(2)?;
-        let poll_uuid = Uuid::parse_str(poll_id.as_str()).unwrap();
-
-        let instance = PollInstance {
-            id: row.try_get(0)?,
-            sent_at: row.try_get(1)?,
-            answers: Vec::new(),
-            poll_uuid: Some(poll_uuid),
-            poll: None,
-        };
+            .fetch_optional(self.pool)
+            .await?
+            .ok_or_else(|| PollError::not_found("poll instance", id))?;
 
-        Ok(instance)
+        instance_from_row(&row)
     }
 
-    pub async fn find_by_poll(&self, poll: Poll) -> Result<Vec<PollInstance>, Box<dyn Error>> {
-        let mut rows = sqlx::query("SELECT * FROM poll_instances WHERE poll_id = $1")
+    pub async fn find_by_poll(&self, poll: Poll) -> Result<Vec<PollInstance>, PollError> {
+        let mut rows = sqlx::query("SELECT * FROM poll_instances WHERE poll_id = $1 ORDER BY id")
             .bind(poll.id.to_string())
             .fetch(self.pool);
 
         let mut instances: Vec<PollInstance> = Vec::new();
 
         while let Some(row) = rows.try_next().await? {
-            let mut instance = PollInstance {
-                id: row.try_get(0)?,
-                sent_at: row.try_get(1)?,
-                answers: Vec::new(),
-                poll_uuid: None,
-                poll: Some(poll.clone()),
-            };
+            let mut instance = instance_from_row(&row)?;
+            instance.poll = Some(poll.clone());
             instance.answers = self.find_answers(instance.id).await?;
             instances.push(instance)
         }
@@ -299,6 +850,152 @@ impl<'a> PollInstanceRepository<'a> {
         Ok(instances)
     }
 
+    // The occurrences of the poll with their messages, the most recent first.
+    pub async fn find_occurrences_by_poll(
+        &self,
+        poll: Poll,
+    ) -> Result<Vec<PollOccurrence>, PollError> {
+        let rows = sqlx::query(
+            "SELECT id, sent_at FROM poll_occurrences WHERE poll_id = $1 ORDER BY sent_at DESC",
+        )
+        .bind(poll.id.to_string())
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut occurrences: Vec<PollOccurrence> = Vec::new();
+        for row in rows {
+            occurrences.push(PollOccurrence {
+                id: row.try_get("id")?,
+                poll_id: poll.id,
+                sent_at: row.try_get("sent_at")?,
+                instances: Vec::new(),
+            });
+        }
+
+        for instance in self.find_by_poll(poll).await? {
+            if let Some(occurrence) = occurrences
+                .iter_mut()
+                .find(|o| Some(o.id) == instance.occurrence_id)
+            {
+                occurrence.instances.push(instance);
+            }
+        }
+
+        Ok(occurrences)
+    }
+
+    // Instances still open or closed since the given date, finalized ones
+    // excepted.
+    pub async fn find_ids_ending_after(&self, since: DateTime<Utc>) -> Result<Vec<i64>, PollError> {
+        let rows = sqlx::query(
+            "
+SELECT id
+FROM poll_instances
+WHERE ends_at >= $1 AND finalized_at IS NULL
+ORDER BY sent_at",
+        )
+        .bind(since)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut ids: Vec<i64> = Vec::new();
+        for row in rows {
+            ids.push(row.try_get("id")?);
+        }
+
+        Ok(ids)
+    }
+
+    pub async fn find_expired_open_ids(&self, at: DateTime<Utc>) -> Result<Vec<i64>, PollError> {
+        let rows = sqlx::query(
+            "SELECT id FROM poll_instances WHERE status = $1 AND ends_at <= $2 ORDER BY ends_at",
+        )
+        .bind(PollInstanceStatus::Open.to_string())
+        .bind(at)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut ids: Vec<i64> = Vec::new();
+        for row in rows {
+            ids.push(row.try_get("id")?);
+        }
+
+        Ok(ids)
+    }
+
+    // Occurrences of polls announcing their results, closed since the given
+    // date and not announced yet.
+    pub async fn find_unannounced_sends(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(Uuid, i64)>, PollError> {
+        let rows = sqlx::query(
+            "
+SELECT pi.poll_id, pi.occurrence_id
+FROM poll_instances pi
+JOIN polls p ON p.id = pi.poll_id
+WHERE p.announce_results AND pi.announced_at IS NULL AND pi.ends_at >= $1
+GROUP BY pi.poll_id, pi.occurrence_id
+HAVING bool_and(pi.status = $2)
+ORDER BY MIN(pi.sent_at)",
+        )
+        .bind(since)
+        .bind(PollInstanceStatus::Closed.to_string())
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut sends: Vec<(Uuid, i64)> = Vec::new();
+        for row in rows {
+            let poll_id: String = row.try_get("poll_id")?;
+            let poll_uuid = Uuid::parse_str(poll_id.as_str()).map_err(PollError::decode)?;
+            sends.push((poll_uuid, row.try_get("occurrence_id")?));
+        }
+
+        Ok(sends)
+    }
+
+    pub async fn find_ids_by_occurrence(&self, occurrence_id: i64) -> Result<Vec<i64>, PollError> {
+        let rows =
+            sqlx::query("SELECT id FROM poll_instances WHERE occurrence_id = $1 ORDER BY id")
+                .bind(occurrence_id)
+                .fetch_all(self.pool)
+                .await?;
+
+        let mut ids: Vec<i64> = Vec::new();
+        for row in rows {
+            ids.push(row.try_get("id")?);
+        }
+
+        Ok(ids)
+    }
+
+    // Marks the instances as announced unless an other task already did,
+    // returns whether they were marked here.
+    pub async fn mark_announced(&self, ids: &[i64], at: DateTime<Utc>) -> Result<bool, PollError> {
+        let marked = sqlx::query(
+            "UPDATE poll_instances SET announced_at = $1 WHERE id = ANY($2) AND announced_at IS NULL",
+        )
+        .bind(at)
+        .bind(ids)
+        .execute(self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(marked > 0)
+    }
+
+    pub async fn unmark_announced(&self, ids: &[i64], at: DateTime<Utc>) -> Result<(), PollError> {
+        sqlx::query(
+            "UPDATE poll_instances SET announced_at = NULL WHERE id = ANY($1) AND announced_at = $2",
+        )
+        .bind(ids)
+        .bind(at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     async fn exists(&self, id: i64) -> bool {
         let row = sqlx::query("SELECT id FROM poll_instances WHERE id = $1")
             .bind(id)
@@ -308,7 +1005,7 @@ impl<'a> PollInstanceRepository<'a> {
         row.is_some()
     }
 
-    async fn create(&self, i: &PollInstance) -> Result<(), Box<dyn Error>> {
+    async fn create(&self, i: &PollInstance) -> Result<(), PollError> {
         self.create_instance(i).await?;
 
         for answer in &i.answers {
@@ -318,22 +1015,51 @@ impl<'a> PollInstanceRepository<'a> {
         Ok(())
     }
 
-    async fn create_instance(&self, i: &PollInstance) -> Result<(), Box<dyn Error>> {
-        sqlx::query("INSERT INTO poll_instances (id, sent_at, poll_id) VALUES ($1, $2, $3)")
-            .bind(i.id)
-            .bind(i.sent_at)
-            .bind(i.poll.clone().unwrap().id.to_string())
-            .execute(self.pool)
-            .await?;
+    // The messages of a send share its scheduled_at, the first one creates the
+    // occurrence and the others join it. Without a send the messages are
+    // grouped by the second they were posted at.
+    async fn create_instance(&self, i: &PollInstance) -> Result<(), PollError> {
+        let poll_id = i.poll.clone().unwrap().id.to_string();
+        let mut tx = self.pool.begin().await?;
+
+        let occurrence = sqlx::query(
+            "
+INSERT INTO poll_occurrences (poll_id, sent_at, scheduled_at)
+VALUES ($1, $2, COALESCE($3, to_timestamp($2)))
+ON CONFLICT (poll_id, scheduled_at) DO UPDATE SET scheduled_at = EXCLUDED.scheduled_at
+RETURNING id",
+        )
+        .bind(&poll_id)
+        .bind(i.sent_at)
+        .bind(i.scheduled_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        let occurrence_id: i64 = occurrence.try_get("id")?;
+
+        sqlx::query(
+            "
+INSERT INTO poll_instances (id, sent_at, poll_id, channel_id, ends_at, status, finalized_at, announced_at, occurrence_id, manual)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(i.id)
+        .bind(i.sent_at)
+        .bind(&poll_id)
+        .bind(i.channel_id)
+        .bind(i.ends_at)
+        .bind(i.status.to_string())
+        .bind(i.finalized_at)
+        .bind(i.announced_at)
+        .bind(occurrence_id)
+        .bind(i.manual)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
 
         Ok(())
     }
 
-    async fn create_answer(
-        &self,
-        a: &PollInstanceAnswer,
-        instance: i64,
-    ) -> Result<(), Box<dyn Error>> {
+    async fn create_answer(&self, a: &PollInstanceAnswer, instance: i64) -> Result<(), PollError> {
         sqlx::query("INSERT INTO poll_instance_answers (id, votes, answer, instance_id) VALUES ($1, $2, $3, $4)")
             .bind(a.discord_answer_id)
             .bind(a.votes)
@@ -344,7 +1070,27 @@ impl<'a> PollInstanceRepository<'a> {
         Ok(())
     }
 
-    async fn update_votes(&self, i: &PollInstance) -> Result<(), Box<dyn Error>> {
+    // Closes the instance with its final counts, unless it was closed in the
+    // meantime. Returns whether it was closed here.
+    pub async fn close(&self, i: &PollInstance) -> Result<bool, PollError> {
+        let mut tx = self.pool.begin().await?;
+
+        let closed = sqlx::query(
+            "UPDATE poll_instances SET status = $1, finalized_at = $2, ends_at = $3 WHERE id = $4 AND status = $5",
+        )
+        .bind(PollInstanceStatus::Closed.to_string())
+        .bind(i.finalized_at)
+        .bind(i.ends_at)
+        .bind(i.id)
+        .bind(PollInstanceStatus::Open.to_string())
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if closed == 0 {
+            return Ok(false);
+        }
+
         for answer in &i.answers {
             sqlx::query(
                 "UPDATE poll_instance_answers SET votes = $1 WHERE id = $2 AND instance_id = $3",
@@ -352,14 +1098,187 @@ impl<'a> PollInstanceRepository<'a> {
             .bind(answer.votes)
             .bind(answer.discord_answer_id)
             .bind(i.id)
-            .execute(self.pool)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Locks the instance against closing until the transaction ends, returns
+    // its status. Votes are only written while the instance is open.
+    async fn lock_status(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: i64,
+    ) -> Result<Option<(PollInstanceStatus, Option<DateTime<Utc>>)>, PollError> {
+        let row =
+            sqlx::query("SELECT status, finalized_at FROM poll_instances WHERE id = $1 FOR SHARE")
+                .bind(id)
+                .fetch_optional(&mut **tx)
+                .await?;
+
+        match row {
+            Some(row) => {
+                let status: String = row.try_get("status")?;
+                Ok(Some((
+                    status.parse().map_err(PollError::decode)?,
+                    row.try_get("finalized_at")?,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Votes are written one at a time so that concurrent writers never drop
+    // each other's votes. The count follows the vote rather than being counted
+    // again: instances sent before the votes were recorded keep their counts
+    // until they are reconciled.
+    pub async fn add_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+        if !matches!(
+            self.lock_status(&mut tx, id).await?,
+            Some((PollInstanceStatus::Open, _))
+        ) {
+            return Ok(());
+        }
+
+        let inserted = sqlx::query(
+            "INSERT INTO poll_votes (instance_id, answer_id, user_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(vote.answer_id)
+        .bind(vote.user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if inserted > 0 {
+            sqlx::query(
+                "UPDATE poll_instance_answers SET votes = COALESCE(votes, 0) + 1 WHERE instance_id = $1 AND id = $2",
+            )
+            .bind(id)
+            .bind(vote.answer_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+        if !matches!(
+            self.lock_status(&mut tx, id).await?,
+            Some((PollInstanceStatus::Open, _))
+        ) {
+            return Ok(());
+        }
+
+        let deleted = sqlx::query(
+            "DELETE FROM poll_votes WHERE instance_id = $1 AND answer_id = $2 AND user_id = $3",
+        )
+        .bind(id)
+        .bind(vote.answer_id)
+        .bind(vote.user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if deleted > 0 {
+            sqlx::query(
+                "UPDATE poll_instance_answers SET votes = GREATEST(COALESCE(votes, 0) - 1, 0) WHERE instance_id = $1 AND id = $2",
+            )
+            .bind(id)
+            .bind(vote.answer_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Applies the votes found missing or gone by a reconciliation. Every vote
+    // is known then, the counts are counted again from them unless the final
+    // counts were stored.
+    pub async fn update_votes(&self, id: i64, changes: &VoteChanges) -> Result<(), PollError> {
+        let mut tx = self.pool.begin().await?;
+        let finalized = match self.lock_status(&mut tx, id).await? {
+            Some((_, finalized_at)) => finalized_at.is_some(),
+            None => return Err(PollError::not_found("poll instance", id)),
+        };
+
+        let answer_ids: Vec<i64> = changes.removed.iter().map(|v| v.answer_id).collect();
+        let user_ids: Vec<i64> = changes.removed.iter().map(|v| v.user_id).collect();
+        sqlx::query(
+            "
+DELETE FROM poll_votes v
+USING UNNEST($2::BIGINT[], $3::BIGINT[]) AS r(answer_id, user_id)
+WHERE v.instance_id = $1 AND v.answer_id = r.answer_id AND v.user_id = r.user_id",
+        )
+        .bind(id)
+        .bind(answer_ids)
+        .bind(user_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        let answer_ids: Vec<i64> = changes.added.iter().map(|v| v.answer_id).collect();
+        let user_ids: Vec<i64> = changes.added.iter().map(|v| v.user_id).collect();
+        sqlx::query(
+            "
+INSERT INTO poll_votes (instance_id, answer_id, user_id)
+SELECT $1, * FROM UNNEST($2::BIGINT[], $3::BIGINT[])
+ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(answer_ids)
+        .bind(user_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        if !finalized {
+            sqlx::query(
+                "
+UPDATE poll_instance_answers pia
+SET votes = (SELECT COUNT(*) FROM poll_votes v WHERE v.instance_id = pia.instance_id AND v.answer_id = pia.id)
+WHERE pia.instance_id = $1",
+            )
+            .bind(id)
+            .execute(&mut *tx)
             .await?;
         }
 
+        tx.commit().await?;
+
         Ok(())
     }
 
-    pub async fn find_answers(&self, id: i64) -> Result<Vec<PollInstanceAnswer>, Box<dyn Error>> {
+    pub async fn find_votes(&self, id: i64) -> Result<Vec<PollVote>, PollError> {
+        let rows = sqlx::query(
+            "SELECT answer_id, user_id FROM poll_votes WHERE instance_id = $1 ORDER BY user_id",
+        )
+        .bind(id)
+        .fetch_all(self.pool)
+        .await?;
+
+        let mut votes: Vec<PollVote> = Vec::new();
+        for row in rows {
+            votes.push(PollVote {
+                answer_id: row.try_get("answer_id")?,
+                user_id: row.try_get("user_id")?,
+            });
+        }
+
+        Ok(votes)
+    }
+
+    pub async fn find_answers(&self, id: i64) -> Result<Vec<PollInstanceAnswer>, PollError> {
         let mut rows = sqlx::query(
             "SELECT id, votes, answer FROM poll_instance_answers WHERE instance_id = $1",
         )
@@ -381,7 +1300,7 @@ impl<'a> PollInstanceRepository<'a> {
     pub async fn find_answers_by_poll_id(
         &self,
         id: Uuid,
-    ) -> Result<Vec<PollInstanceAnswer>, Box<dyn Error>> {
+    ) -> Result<Vec<PollInstanceAnswer>, PollError> {
         let mut poll_instance_answers: Vec<PollInstanceAnswer> = Vec::new();
 
         let mut rows = sqlx::query(
@@ -405,4 +1324,286 @@ impl<'a> PollInstanceRepository<'a> {
 
         Ok(poll_instance_answers)
     }
+
+    // Sends of the poll, from and to are timestamps in seconds, the range
+    // is half open.
+    pub async fn count_occurrences(
+        &self,
+        poll_id: Uuid,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<i64, PollError> {
+        let row = sqlx::query(
+            "
+SELECT COUNT(*)
+FROM poll_occurrences
+WHERE poll_id = $1
+AND ($2::BIGINT IS NULL OR sent_at >= $2)
+AND ($3::BIGINT IS NULL OR sent_at < $3)",
+        )
+        .bind(poll_id.to_string())
+        .bind(from)
+        .bind(to)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(row.try_get(0)?)
+    }
+
+    // Votes of each answer over the sends of the poll, the answers of every
+    // message of a send are merged. An answer sent before it was renamed is
+    // counted under its current name, through the first rename after the
+    // send.
+    pub async fn find_answer_stats(
+        &self,
+        poll_id: Uuid,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Vec<AnswerStats>, PollError> {
+        let mut rows = sqlx::query(
+            "
+WITH sent_answers AS (
+    SELECT po.id AS occurrence_id, pia.answer AS sent_answer, COALESCE(pia.votes, 0) AS votes,
+        COALESCE((
+            SELECT a.answer
+            FROM answer_renames ar
+            JOIN answers a ON a.id = ar.answer_id
+            WHERE a.poll_id = po.poll_id AND ar.previous_answer = pia.answer
+            AND ar.renamed_at >= to_timestamp(po.sent_at)
+            ORDER BY ar.renamed_at
+            LIMIT 1
+        ), pia.answer) AS answer
+    FROM poll_occurrences po
+    JOIN poll_instances pi ON pi.occurrence_id = po.id
+    JOIN poll_instance_answers pia ON pia.instance_id = pi.id
+    WHERE po.poll_id = $1
+    AND ($2::BIGINT IS NULL OR po.sent_at >= $2)
+    AND ($3::BIGINT IS NULL OR po.sent_at < $3)
+),
+occurrence_answers AS (
+    SELECT occurrence_id, answer, SUM(votes) AS votes
+    FROM sent_answers
+    GROUP BY occurrence_id, answer
+),
+ranked AS (
+    SELECT answer, votes, votes > 0 AND votes = MAX(votes) OVER (PARTITION BY occurrence_id) AS won
+    FROM occurrence_answers
+),
+names AS (
+    SELECT answer, array_agg(DISTINCT sent_answer ORDER BY sent_answer) FILTER (WHERE sent_answer <> answer) AS previous_answers
+    FROM sent_answers
+    GROUP BY answer
+)
+SELECT r.answer, COALESCE(n.previous_answers, '{}') AS previous_answers, SUM(r.votes)::BIGINT AS total_votes,
+    COUNT(*) AS instances, COUNT(*) FILTER (WHERE r.won) AS wins
+FROM ranked r
+JOIN names n ON n.answer = r.answer
+GROUP BY r.answer, n.previous_answers
+ORDER BY total_votes DESC, r.answer",
+        )
+        .bind(poll_id.to_string())
+        .bind(from)
+        .bind(to)
+        .fetch(self.pool);
+
+        let mut stats: Vec<AnswerStats> = Vec::new();
+        while let Some(row) = rows.try_next().await? {
+            stats.push(AnswerStats {
+                answer: row.try_get("answer")?,
+                previous_answers: row.try_get("previous_answers")?,
+                total_votes: row.try_get("total_votes")?,
+                instances: row.try_get("instances")?,
+                wins: row.try_get("wins")?,
+            });
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::init_db;
+    use crate::poll::cron_filter::filter;
+    use chrono::Duration;
+    use std::env;
+
+    // the tests writing to the database of DATABASE_URL are run with
+    // make db-tests
+    async fn connect() -> PgPool {
+        let database = env::var("DATABASE_URL").expect("Expected DATABASE_URL in the environment");
+        init_db(&database).await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database"]
+    async fn test_claim_send_once() {
+        let pool = connect().await;
+        let repository = PollRepository { pool: &pool };
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .question(String::from("claimed twice"));
+        repository.save(&poll, &[]).await.unwrap();
+
+        let scheduled_at = Utc::now();
+        assert!(repository
+            .claim_send(poll.id, scheduled_at, "sender:1", scheduled_at)
+            .await
+            .unwrap());
+        assert!(!repository
+            .claim_send(poll.id, scheduled_at, "sender:2", scheduled_at)
+            .await
+            .unwrap());
+        assert_eq!(
+            1,
+            repository
+                .find_by_id(poll.id)
+                .await
+                .unwrap()
+                .occurrences_sent
+        );
+
+        // the claim is never completed
+        let stuck = repository
+            .find_stuck_sends(poll.id, scheduled_at + Duration::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(1, stuck.len());
+        assert_eq!(None, stuck[0].id);
+        assert_eq!("claimed by sender:1 and never completed", stuck[0].error);
+
+        repository.delete_poll(poll.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database"]
+    async fn test_claim_send_requests_at_the_same_time() {
+        let pool = connect().await;
+        let repository = PollRepository { pool: &pool };
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .question(String::from("requested twice"));
+        repository.save(&poll, &[]).await.unwrap();
+
+        let first = repository.create_send_request(poll.id).await.unwrap();
+        let second = repository.create_send_request(poll.id).await.unwrap();
+        sqlx::query("UPDATE poll_send_requests SET requested_at = $1 WHERE id = $2")
+            .bind(first.requested_at)
+            .bind(second.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (sends, dropped) = repository
+            .claim_send_requests("sender:1", Utc::now())
+            .await
+            .unwrap();
+        let sends: Vec<&PollSend> = sends.iter().filter(|s| s.poll_id == poll.id).collect();
+        let dropped: Vec<&PollSendRequest> =
+            dropped.iter().filter(|r| r.poll_id == poll.id).collect();
+        assert_eq!(1, sends.len());
+        assert_eq!(first.requested_at, sends[0].scheduled_at);
+        assert!(sends[0].manual);
+        assert_eq!(
+            vec![second.id],
+            dropped.iter().map(|r| r.id).collect::<Vec<_>>()
+        );
+
+        repository.delete_poll(poll.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database"]
+    async fn test_answer_stats_renamed_twice() {
+        let pool = connect().await;
+        let repository = PollRepository { pool: &pool };
+        let instance_repository = PollInstanceRepository { pool: &pool };
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .question(String::from("renamed twice"))
+            .answers(vec![String::from("sushi"), String::from("pizza")]);
+        repository.save(&poll, &[]).await.unwrap();
+
+        let mut instance = PollInstance::new(poll.clone());
+        instance.id = Utc::now().timestamp_micros();
+        instance.sent_at = Utc::now().timestamp() - 60;
+        instance.scheduled_at = Some(Utc::now() - Duration::minutes(1));
+        instance.answers = vec![
+            PollInstanceAnswer {
+                answer: String::from("sushi"),
+                discord_answer_id: 1,
+                votes: 3,
+            },
+            PollInstanceAnswer {
+                answer: String::from("pizza"),
+                discord_answer_id: 2,
+                votes: 1,
+            },
+        ];
+        instance_repository.save(instance).await.unwrap();
+
+        let rename = |from: &str, to: &str| AnswerRename {
+            from: String::from(from),
+            to: String::from(to),
+        };
+        let ramen = poll
+            .clone()
+            .answers(vec![String::from("ramen"), String::from("pizza")]);
+        repository
+            .save(&ramen, &[rename("sushi", "ramen")])
+            .await
+            .unwrap();
+        let udon = poll
+            .clone()
+            .answers(vec![String::from("udon"), String::from("pizza")]);
+        repository
+            .save(&udon, &[rename("ramen", "udon")])
+            .await
+            .unwrap();
+
+        let stats = instance_repository
+            .find_answer_stats(poll.id, None, None)
+            .await
+            .unwrap();
+        assert_eq!(2, stats.len());
+        assert_eq!("udon", stats[0].answer);
+        assert_eq!(vec![String::from("sushi")], stats[0].previous_answers);
+        assert_eq!(3, stats[0].total_votes);
+        assert_eq!("pizza", stats[1].answer);
+
+        repository.delete_poll(poll.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a database"]
+    async fn test_update_keeps_sent() {
+        let pool = connect().await;
+        let repository = PollRepository { pool: &pool };
+        let poll = Poll::new()
+            .cron(String::from("0 9 * * *"))
+            .question(String::from("sent once"))
+            .onetime(true);
+        repository.save(&poll, &[]).await.unwrap();
+
+        let scheduled_at = Utc::now();
+        assert!(repository
+            .claim_send(poll.id, scheduled_at, "sender:1", scheduled_at)
+            .await
+            .unwrap());
+
+        // an update rebuilds the poll without what was sent
+        let updated = Poll::new()
+            .id(poll.id)
+            .cron(String::from("* * * * *"))
+            .question(String::from("sent once, edited"))
+            .onetime(true);
+        repository.save(&updated, &[]).await.unwrap();
+
+        let saved = repository.find_by_id(poll.id).await.unwrap();
+        assert!(saved.sent);
+        assert!(filter(vec![saved], &(scheduled_at + Duration::minutes(2))).is_empty());
+
+        repository.delete_poll(poll.id).await.unwrap();
+    }
 }
@@ -0,0 +1,239 @@
+use crate::poll::domain::Poll;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+// Order of a list of polls, a leading `-` sorts in descending order. Polls
+// sorting the same are ordered by id so that pages never overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PollSort {
+    #[default]
+    CreatedAt,
+    CreatedAtDesc,
+    Question,
+    QuestionDesc,
+}
+
+impl PollSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::CreatedAt | Self::CreatedAtDesc => "created_at",
+            Self::Question | Self::QuestionDesc => "question",
+        }
+    }
+
+    pub fn is_descending(&self) -> bool {
+        matches!(self, Self::CreatedAtDesc | Self::QuestionDesc)
+    }
+}
+
+impl fmt::Display for PollSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreatedAt => write!(f, "created_at"),
+            Self::CreatedAtDesc => write!(f, "-created_at"),
+            Self::Question => write!(f, "question"),
+            Self::QuestionDesc => write!(f, "-question"),
+        }
+    }
+}
+
+impl FromStr for PollSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created_at" => Ok(Self::CreatedAt),
+            "-created_at" => Ok(Self::CreatedAtDesc),
+            "question" => Ok(Self::Question),
+            "-question" => Ok(Self::QuestionDesc),
+            _ => Err(format!("unknown sort: {}", s)),
+        }
+    }
+}
+
+// Value of the sorted column for the last poll of a page.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKey {
+    CreatedAt(DateTime<Utc>),
+    Question(String),
+}
+
+// Where the next page starts, it is only valid for the sort it was made for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollCursor {
+    pub sort: PollSort,
+    pub key: SortKey,
+    pub id: Uuid,
+}
+
+impl PollCursor {
+    pub fn after(sort: PollSort, poll: &Poll) -> PollCursor {
+        let key = match sort {
+            PollSort::CreatedAt | PollSort::CreatedAtDesc => SortKey::CreatedAt(poll.created_at),
+            PollSort::Question | PollSort::QuestionDesc => SortKey::Question(poll.question.clone()),
+        };
+
+        PollCursor {
+            sort,
+            key,
+            id: poll.id,
+        }
+    }
+
+    // Opaque to clients, the sort, the key and the id joined then encoded.
+    pub fn encode(&self) -> String {
+        let key = match &self.key {
+            SortKey::CreatedAt(at) => at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            SortKey::Question(question) => question.clone(),
+        };
+
+        URL_SAFE_NO_PAD.encode(format!("{}|{}|{}", self.sort, key, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Result<PollCursor, String> {
+        let invalid = || String::from("invalid cursor");
+
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        // questions may contain the separator, neither the sort nor the id do
+        let (sort, rest) = decoded.split_once('|').ok_or_else(invalid)?;
+        let (key, id) = rest.rsplit_once('|').ok_or_else(invalid)?;
+
+        let sort: PollSort = sort.parse().map_err(|_| invalid())?;
+        let key = match sort {
+            PollSort::CreatedAt | PollSort::CreatedAtDesc => SortKey::CreatedAt(
+                DateTime::parse_from_rfc3339(key)
+                    .map_err(|_| invalid())?
+                    .with_timezone(&Utc),
+            ),
+            PollSort::Question | PollSort::QuestionDesc => SortKey::Question(key.to_string()),
+        };
+
+        Ok(PollCursor {
+            sort,
+            key,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PollFilter {
+    // matches either the name or the id of the guild
+    pub guild: Option<String>,
+    pub channel: Option<String>,
+    pub onetime: Option<bool>,
+    pub sent: Option<bool>,
+    // searched in the question, case insensitive
+    pub question: Option<String>,
+    // restricts the polls to these guilds, e.g. the ones of the caller
+    pub guild_ids: Option<Vec<i64>>,
+    pub sort: PollSort,
+    pub after: Option<PollCursor>,
+    pub limit: i64,
+}
+
+impl Default for PollFilter {
+    fn default() -> Self {
+        PollFilter {
+            guild: None,
+            channel: None,
+            onetime: None,
+            sent: None,
+            question: None,
+            guild_ids: None,
+            sort: PollSort::default(),
+            after: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PollPage {
+    pub polls: Vec<Poll>,
+    // none on the last page
+    pub next_cursor: Option<PollCursor>,
+}
+
+// Pattern matching the text anywhere, the wildcards of the text are matched
+// literally.
+pub fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort() {
+        for sort in [
+            PollSort::CreatedAt,
+            PollSort::CreatedAtDesc,
+            PollSort::Question,
+            PollSort::QuestionDesc,
+        ] {
+            assert_eq!(Ok(sort), sort.to_string().parse::<PollSort>());
+        }
+        assert!("-id".parse::<PollSort>().is_err());
+        assert_eq!("question", PollSort::QuestionDesc.column());
+        assert!(PollSort::QuestionDesc.is_descending());
+        assert!(!PollSort::CreatedAt.is_descending());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let poll = Poll::new()
+            .question(String::from("lunch | dinner?"))
+            .created_at(
+                DateTime::parse_from_rfc3339("2025-01-15T09:00:00.123456Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            );
+
+        for sort in [PollSort::CreatedAtDesc, PollSort::Question] {
+            let cursor = PollCursor::after(sort, &poll);
+            assert_eq!(Ok(cursor.clone()), PollCursor::decode(&cursor.encode()));
+        }
+
+        let cursor = PollCursor::after(PollSort::Question, &poll);
+        assert_eq!(
+            SortKey::Question(String::from("lunch | dinner?")),
+            cursor.key
+        );
+        assert_eq!(poll.id, cursor.id);
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        assert!(PollCursor::decode("not a cursor").is_err());
+        assert!(PollCursor::decode(&URL_SAFE_NO_PAD.encode("question|abc")).is_err());
+        assert!(PollCursor::decode(
+            &URL_SAFE_NO_PAD.encode(format!("created_at|yesterday|{}", Uuid::new_v4()))
+        )
+        .is_err());
+        assert!(
+            PollCursor::decode(&URL_SAFE_NO_PAD.encode(format!("id|abc|{}", Uuid::new_v4())))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_contains_pattern() {
+        assert_eq!("%lunch%", contains_pattern("lunch"));
+        assert_eq!("%100\\% \\_sure\\\\%", contains_pattern("100% _sure\\"));
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Duration, Utc};
+
+// a send is given up after this many failed attempts
+pub const MAX_SEND_ATTEMPTS: i32 = 5;
+const BASE_BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 60 * 60;
+// a send still claimed after that long was left behind by a sender that
+// stopped while posting it
+pub const STUCK_CLAIM_SECONDS: i64 = 10 * 60;
+
+// When the send is attempted again after its `attempt`th failure, none when
+// it is given up. The delay doubles after each failure.
+pub fn next_attempt_at(attempt: i32, failed_at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if attempt >= MAX_SEND_ATTEMPTS {
+        return None;
+    }
+
+    let exponent = (attempt.max(1) - 1) as u32;
+    let backoff = BASE_BACKOFF_SECONDS
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(MAX_BACKOFF_SECONDS);
+
+    Some(failed_at + Duration::seconds(backoff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_attempt_at() {
+        let now = DateTime::parse_from_rfc3339("2025-01-15T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let delays: Vec<i64> = (1..MAX_SEND_ATTEMPTS)
+            .map(|attempt| (next_attempt_at(attempt, now).unwrap() - now).num_seconds())
+            .collect();
+        assert_eq!(vec![30, 60, 120, 240], delays);
+
+        assert_eq!(None, next_attempt_at(MAX_SEND_ATTEMPTS, now));
+    }
+}
@@ -0,0 +1,190 @@
+// Votes of an answer across the sends of a poll. Votes sent under a previous
+// name of the answer are counted for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnswerStats {
+    pub answer: String,
+    pub previous_answers: Vec<String>,
+    pub total_votes: i64,
+    // sends the answer was part of
+    pub instances: i64,
+    // sends the answer had the most votes in, ties included
+    pub wins: i64,
+}
+
+impl AnswerStats {
+    pub fn mean_votes(&self) -> f64 {
+        if self.instances == 0 {
+            return 0.0;
+        }
+
+        self.total_votes as f64 / self.instances as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollStats {
+    // sends of the poll
+    pub occurrences: i64,
+    pub answers: Vec<AnswerStats>,
+}
+
+impl PollStats {
+    pub fn total_votes(&self) -> i64 {
+        self.answers.iter().map(|a| a.total_votes).sum()
+    }
+
+    // Share of all the votes going to the answer, between 0 and 1.
+    pub fn share(&self, answer: &AnswerStats) -> f64 {
+        let total_votes = self.total_votes();
+        if total_votes == 0 {
+            return 0.0;
+        }
+
+        answer.total_votes as f64 / total_votes as f64
+    }
+}
+
+// An answer renamed on update, its votes are counted for its new name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnswerRename {
+    pub from: String,
+    pub to: String,
+}
+
+// How the saved answers of a poll are turned into the new ones. Answers are
+// only renamed when asked to, keeping their votes in the statistics, the
+// others are deleted and created.
+#[derive(Debug, Default, PartialEq)]
+pub struct AnswerChanges {
+    // position of the saved answer and its new name
+    pub renamed: Vec<(usize, String)>,
+    pub created: Vec<String>,
+    // positions of the saved answers
+    pub deleted: Vec<usize>,
+}
+
+pub fn answer_changes(
+    saved: &[String],
+    answers: &[String],
+    renames: &[AnswerRename],
+) -> AnswerChanges {
+    let mut changes = AnswerChanges::default();
+
+    for rename in renames {
+        if let Some(i) = saved.iter().position(|previous| *previous == rename.from) {
+            changes.renamed.push((i, rename.to.clone()));
+        }
+    }
+
+    changes.created = answers
+        .iter()
+        .filter(|answer| {
+            !saved.contains(answer) && !changes.renamed.iter().any(|(_, to)| to == *answer)
+        })
+        .cloned()
+        .collect();
+
+    changes.deleted = saved
+        .iter()
+        .enumerate()
+        .filter(|(i, previous)| {
+            !answers.contains(previous) && !changes.renamed.iter().any(|(r, _)| r == i)
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn answer_stats(answer: &str, total_votes: i64, instances: i64) -> AnswerStats {
+        AnswerStats {
+            answer: String::from(answer),
+            previous_answers: vec![],
+            total_votes,
+            instances,
+            wins: 0,
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = PollStats {
+            occurrences: 4,
+            answers: vec![answer_stats("pizza", 9, 4), answer_stats("sushi", 3, 2)],
+        };
+
+        assert_eq!(12, stats.total_votes());
+        assert_eq!(2.25, stats.answers[0].mean_votes());
+        assert_eq!(0.75, stats.share(&stats.answers[0]));
+        assert_eq!(0.25, stats.share(&stats.answers[1]));
+    }
+
+    #[test]
+    fn test_stats_without_votes() {
+        let stats = PollStats {
+            occurrences: 0,
+            answers: vec![answer_stats("pizza", 0, 0)],
+        };
+
+        assert_eq!(0.0, stats.answers[0].mean_votes());
+        assert_eq!(0.0, stats.share(&stats.answers[0]));
+    }
+
+    #[test]
+    fn test_answer_changes() {
+        let saved = strings(&["pizza", "sushi", "tacos"]);
+        let rename = |from: &str, to: &str| AnswerRename {
+            from: String::from(from),
+            to: String::from(to),
+        };
+
+        assert_eq!(
+            AnswerChanges::default(),
+            answer_changes(&saved, &saved, &[])
+        );
+
+        // replaced in place without a rename
+        assert_eq!(
+            AnswerChanges {
+                created: vec![String::from("ramen")],
+                deleted: vec![1],
+                ..Default::default()
+            },
+            answer_changes(&saved, &strings(&["pizza", "ramen", "tacos"]), &[])
+        );
+
+        // renamed, wherever the new name is
+        assert_eq!(
+            AnswerChanges {
+                renamed: vec![(1, String::from("ramen"))],
+                ..Default::default()
+            },
+            answer_changes(
+                &saved,
+                &strings(&["pizza", "tacos", "ramen"]),
+                &[rename("sushi", "ramen")]
+            )
+        );
+
+        assert_eq!(
+            AnswerChanges {
+                renamed: vec![(2, String::from("ramen"))],
+                created: vec![String::from("curry")],
+                deleted: vec![0],
+            },
+            answer_changes(
+                &saved,
+                &strings(&["sushi", "curry", "ramen"]),
+                &[rename("tacos", "ramen")]
+            )
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::poll::domain::PollInstance;
+use std::collections::HashSet;
+
+// discord rejects longer messages
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+pub const PLACEHOLDERS: [&str; 5] = ["question", "winner", "results", "total_votes", "turnout"];
+pub const DEFAULT_TEMPLATE: &str = "**{question}**
+Winner: {winner}
+{results}
+{total_votes} votes from {turnout} voters";
+
+// Renders the results of a send, made of one instance per poll message.
+// Placeholders:
+// - {question}
+// - {winner}: the answer with the most votes, all of them on a tie
+// - {results}: one line per answer with its votes and percentage
+// - {total_votes}
+// - {turnout}: number of users who voted
+pub fn render(template: &str, question: &str, instances: &[PollInstance]) -> String {
+    let answers: Vec<(&str, i32)> = instances
+        .iter()
+        .flat_map(|i| i.answers.iter().map(|a| (a.answer.as_str(), a.votes)))
+        .collect();
+
+    let total_votes: i32 = answers.iter().map(|(_, votes)| votes).sum();
+    let turnout = instances
+        .iter()
+        .flat_map(|i| i.votes.iter().map(|v| v.user_id))
+        .collect::<HashSet<i64>>()
+        .len();
+
+    let max_votes = answers.iter().map(|(_, votes)| *votes).max().unwrap_or(0);
+    let winner = if max_votes == 0 {
+        String::from("no votes")
+    } else {
+        answers
+            .iter()
+            .filter(|(_, votes)| *votes == max_votes)
+            .map(|(answer, _)| *answer)
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+
+    let results = answers
+        .iter()
+        .map(|(answer, votes)| {
+            format!(
+                "- {}: {} ({}%)",
+                answer,
+                votes,
+                percentage(*votes, total_votes)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let message = template
+        .replace("{question}", question)
+        .replace("{winner}", &winner)
+        .replace("{results}", &results)
+        .replace("{total_votes}", &total_votes.to_string())
+        .replace("{turnout}", &turnout.to_string());
+
+    message.chars().take(MAX_MESSAGE_LENGTH).collect()
+}
+
+// Placeholders of the template that are not replaced when rendering.
+pub fn unknown_placeholders(template: &str) -> Vec<String> {
+    let mut unknown: Vec<String> = Vec::new();
+
+    for part in template.split('{').skip(1) {
+        if let Some((name, _)) = part.split_once('}') {
+            if !PLACEHOLDERS.contains(&name) {
+                unknown.push(name.to_string());
+            }
+        }
+    }
+
+    unknown
+}
+
+fn percentage(votes: i32, total_votes: i32) -> i32 {
+    if total_votes == 0 {
+        return 0;
+    }
+
+    (votes as f64 * 100.0 / total_votes as f64).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::domain::{Poll, PollInstanceAnswer};
+
+    fn create_instance(answers: Vec<&str>) -> PollInstance {
+        let mut instance = PollInstance::new(Poll::new());
+        instance.answers = answers
+            .into_iter()
+            .enumerate()
+            .map(|(i, answer)| PollInstanceAnswer {
+                discord_answer_id: i as i64 + 1,
+                answer: answer.to_string(),
+                votes: 0,
+            })
+            .collect();
+        instance
+    }
+
+    #[test]
+    fn test_render_default_template() {
+        let mut instance = create_instance(vec!["pizza", "sushi", "tacos"]);
+        instance.add_vote(1, 100).unwrap();
+        instance.add_vote(1, 101).unwrap();
+        instance.add_vote(2, 101).unwrap();
+
+        let got = render(DEFAULT_TEMPLATE, "lunch?", &[instance]);
+
+        assert_eq!(
+            "**lunch?**
+Winner: pizza
+- pizza: 2 (67%)
+- sushi: 1 (33%)
+- tacos: 0 (0%)
+3 votes from 2 voters",
+            got
+        );
+    }
+
+    #[test]
+    fn test_render_tie_across_messages() {
+        let mut first = create_instance(vec!["pizza"]);
+        first.add_vote(1, 100).unwrap();
+        let mut second = create_instance(vec!["sushi"]);
+        second.add_vote(1, 101).unwrap();
+
+        let got = render("{winner} ({turnout})", "lunch?", &[first, second]);
+
+        assert_eq!("pizza, sushi (2)", got);
+    }
+
+    #[test]
+    fn test_render_no_votes() {
+        let instance = create_instance(vec!["pizza"]);
+
+        let got = render("{winner}: {results}", "lunch?", &[instance]);
+
+        assert_eq!("no votes: - pizza: 0 (0%)", got);
+    }
+
+    #[test]
+    fn test_render_truncated() {
+        let instance = create_instance(vec!["pizza"]);
+
+        let got = render(&"{question}".repeat(100), &"?".repeat(100), &[instance]);
+
+        assert_eq!(MAX_MESSAGE_LENGTH, got.chars().count());
+    }
+
+    #[test]
+    fn test_unknown_placeholders() {
+        assert!(unknown_placeholders(DEFAULT_TEMPLATE).is_empty());
+        assert_eq!(
+            vec!["winners", "votes"],
+            unknown_placeholders("{winners} with {votes} {total_votes}")
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use crate::poll::domain::{AnswersError, PollInstance, PollInstanceStatus, PollVote};
+use crate::poll::error::PollError;
+use crate::poll::poll_instance_use_cases::PollUseCases;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+
+// a vote failing on database errors that many times is dropped, the
+// reconciliation corrects the instance later on
+pub const MAX_VOTE_ATTEMPTS: u32 = 3;
+// doubled after each failed attempt
+pub const VOTE_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// A vote event received by the bot, the poll is identified by its message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add {
+        message_id: u64,
+        answer_id: u64,
+        user_id: u64,
+    },
+    Remove {
+        message_id: u64,
+        answer_id: u64,
+        user_id: u64,
+    },
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (event, message_id, answer_id, user_id) = match self {
+            Self::Add {
+                message_id,
+                answer_id,
+                user_id,
+            } => ("add", message_id, answer_id, user_id),
+            Self::Remove {
+                message_id,
+                answer_id,
+                user_id,
+            } => ("remove", message_id, answer_id, user_id),
+        };
+
+        write!(
+            f,
+            "event={} message_id={} answer_id={} user_id={}",
+            event, message_id, answer_id, user_id
+        )
+    }
+}
+
+// Why a vote event could not be applied.
+#[derive(Debug)]
+pub enum VoteError {
+    // the message is not a poll sent by this system
+    UnknownMessage,
+    // the answer is not part of the poll
+    UnknownAnswer,
+    // the poll has ended, its votes are no longer tracked
+    ClosedInstance,
+    Database(PollError),
+}
+
+impl VoteError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Database(_))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            Self::UnknownMessage => "unknown_message",
+            Self::UnknownAnswer => "unknown_answer",
+            Self::ClosedInstance => "closed_instance",
+            Self::Database(_) => "database_error",
+        }
+    }
+}
+
+impl fmt::Display for VoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMessage => write!(f, "poll instance not found"),
+            Self::UnknownAnswer => write!(f, "answer not found"),
+            Self::ClosedInstance => write!(f, "poll instance closed"),
+            Self::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<PollError> for VoteError {
+    fn from(e: PollError) -> Self {
+        match e {
+            PollError::NotFound(_) => VoteError::UnknownMessage,
+            e => VoteError::Database(e),
+        }
+    }
+}
+
+impl From<AnswersError> for VoteError {
+    fn from(_: AnswersError) -> Self {
+        VoteError::UnknownAnswer
+    }
+}
+
+// Where the votes are read from and written to.
+pub trait VoteStore {
+    fn find_instance(
+        &self,
+        id: i64,
+    ) -> impl Future<Output = Result<PollInstance, PollError>> + Send;
+
+    fn add_vote(
+        &self,
+        id: i64,
+        vote: &PollVote,
+    ) -> impl Future<Output = Result<(), PollError>> + Send;
+
+    fn remove_vote(
+        &self,
+        id: i64,
+        vote: &PollVote,
+    ) -> impl Future<Output = Result<(), PollError>> + Send;
+}
+
+impl VoteStore for PollUseCases<'_> {
+    async fn find_instance(&self, id: i64) -> Result<PollInstance, PollError> {
+        self.get_poll_instance_by_id(id).await
+    }
+
+    async fn add_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        PollUseCases::add_vote(self, id, vote).await
+    }
+
+    async fn remove_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+        PollUseCases::remove_vote(self, id, vote).await
+    }
+}
+
+// Applies the vote events one at a time. A failing event is skipped so that
+// it never stops the votes of the other polls from being tracked.
+pub struct VoteManager<S> {
+    store: S,
+    retry_delay: Duration,
+}
+
+impl<S: VoteStore> VoteManager<S> {
+    pub fn new(store: S) -> VoteManager<S> {
+        VoteManager {
+            store,
+            retry_delay: VOTE_RETRY_DELAY,
+        }
+    }
+
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    // Runs until every sender of the channel is dropped.
+    pub async fn run(&self, commands: &mut Receiver<Command>) {
+        while let Some(command) = commands.recv().await {
+            if let Err(e) = self.handle(&command).await {
+                eprintln!(
+                    "[manager] vote skipped {} reason={} error=\"{}\"",
+                    command,
+                    e.reason(),
+                    e
+                );
+            }
+        }
+    }
+
+    // Applies the event, retrying on database errors.
+    pub async fn handle(&self, command: &Command) -> Result<(), VoteError> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+
+        loop {
+            match self.apply(command).await {
+                Err(e) if e.is_retryable() && attempt < MAX_VOTE_ATTEMPTS => {
+                    eprintln!(
+                        "[manager] vote failed {} attempt={} retry_in_ms={} error=\"{}\"",
+                        command,
+                        attempt,
+                        delay.as_millis(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn apply(&self, command: &Command) -> Result<(), VoteError> {
+        let (message_id, answer_id, user_id) = match command {
+            Command::Add {
+                message_id,
+                answer_id,
+                user_id,
+            }
+            | Command::Remove {
+                message_id,
+                answer_id,
+                user_id,
+            } => (*message_id as i64, *answer_id as i64, *user_id as i64),
+        };
+
+        // only the vote is written, the instance is read to check the answer
+        let mut instance = self.store.find_instance(message_id).await?;
+        if instance.status == PollInstanceStatus::Closed {
+            return Err(VoteError::ClosedInstance);
+        }
+
+        let vote = PollVote { answer_id, user_id };
+        match command {
+            Command::Add { .. } => {
+                instance.add_vote(answer_id, user_id)?;
+                self.store.add_vote(instance.id, &vote).await?;
+            }
+            Command::Remove { .. } => {
+                instance.remove_vote(answer_id, user_id)?;
+                self.store.remove_vote(instance.id, &vote).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::domain::{Poll, PollInstanceAnswer, PollVote};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    // instances kept in memory, the next saves fail as many times as set
+    struct InMemoryInstances {
+        instances: Mutex<HashMap<i64, PollInstance>>,
+        failing_saves: Mutex<u32>,
+    }
+
+    impl InMemoryInstances {
+        fn new(instances: Vec<PollInstance>) -> InMemoryInstances {
+            InMemoryInstances {
+                instances: Mutex::new(instances.into_iter().map(|i| (i.id, i)).collect()),
+                failing_saves: Mutex::new(0),
+            }
+        }
+
+        fn fail_saves(&self, count: u32) {
+            *self.failing_saves.lock().unwrap() = count;
+        }
+
+        fn votes(&self, id: i64) -> Vec<PollVote> {
+            self.instances.lock().unwrap()[&id].votes.clone()
+        }
+
+        fn save(
+            &self,
+            id: i64,
+            change: impl FnOnce(&mut PollInstance) -> Result<(), AnswersError>,
+        ) -> Result<(), PollError> {
+            let mut failing_saves = self.failing_saves.lock().unwrap();
+            if *failing_saves > 0 {
+                *failing_saves -= 1;
+                return Err(PollError::Database(sqlx::Error::PoolTimedOut));
+            }
+
+            let mut instances = self.instances.lock().unwrap();
+            let instance = instances
+                .get_mut(&id)
+                .ok_or_else(|| PollError::not_found("poll instance", id))?;
+            change(instance).map_err(|_| PollError::not_found("answer", id))
+        }
+    }
+
+    impl VoteStore for &InMemoryInstances {
+        async fn find_instance(&self, id: i64) -> Result<PollInstance, PollError> {
+            self.instances
+                .lock()
+                .unwrap()
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| PollError::not_found("poll instance", id))
+        }
+
+        async fn add_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+            self.save(id, |instance| {
+                instance.add_vote(vote.answer_id, vote.user_id)
+            })
+        }
+
+        async fn remove_vote(&self, id: i64, vote: &PollVote) -> Result<(), PollError> {
+            self.save(id, |instance| {
+                instance.remove_vote(vote.answer_id, vote.user_id)
+            })
+        }
+    }
+
+    fn create_instance(id: i64) -> PollInstance {
+        let mut instance = PollInstance::new(Poll::new());
+        instance.id = id;
+        instance.answers = vec![
+            PollInstanceAnswer {
+                discord_answer_id: 1,
+                answer: String::from("one"),
+                votes: 0,
+            },
+            PollInstanceAnswer {
+                discord_answer_id: 2,
+                answer: String::from("two"),
+                votes: 0,
+            },
+        ];
+        instance
+    }
+
+    fn add(message_id: u64, answer_id: u64, user_id: u64) -> Command {
+        Command::Add {
+            message_id,
+            answer_id,
+            user_id,
+        }
+    }
+
+    // Sends the commands through the channel and runs the manager until they
+    // are all handled.
+    async fn run(store: &InMemoryInstances, commands: Vec<Command>) {
+        let (tx, mut rx) = mpsc::channel::<Command>(32);
+        for command in commands {
+            tx.send(command).await.unwrap();
+        }
+        drop(tx);
+
+        VoteManager::new(store)
+            .retry_delay(Duration::ZERO)
+            .run(&mut rx)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_votes() {
+        let store = InMemoryInstances::new(vec![create_instance(10)]);
+
+        run(
+            &store,
+            vec![
+                add(10, 1, 100),
+                add(10, 2, 100),
+                add(10, 1, 101),
+                Command::Remove {
+                    message_id: 10,
+                    answer_id: 1,
+                    user_id: 100,
+                },
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            vec![
+                PollVote {
+                    answer_id: 2,
+                    user_id: 100
+                },
+                PollVote {
+                    answer_id: 1,
+                    user_id: 101
+                },
+            ],
+            store.votes(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_unknown_events() {
+        let store = InMemoryInstances::new(vec![create_instance(10)]);
+
+        // votes on other messages and answers do not stop the next ones
+        run(
+            &store,
+            vec![add(20, 1, 100), add(10, 3, 100), add(10, 1, 101)],
+        )
+        .await;
+
+        assert_eq!(
+            vec![PollVote {
+                answer_id: 1,
+                user_id: 101
+            }],
+            store.votes(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_closed_instances() {
+        let mut closed = create_instance(20);
+        closed.close();
+        let store = InMemoryInstances::new(vec![create_instance(10), closed]);
+        let manager = VoteManager::new(&store).retry_delay(Duration::ZERO);
+
+        assert!(matches!(
+            manager.handle(&add(20, 1, 100)).await,
+            Err(VoteError::ClosedInstance)
+        ));
+        assert_eq!(Vec::<PollVote>::new(), store.votes(20));
+
+        run(&store, vec![add(20, 1, 100), add(10, 1, 100)]).await;
+        assert_eq!(Vec::<PollVote>::new(), store.votes(20));
+        assert_eq!(1, store.votes(10).len());
+    }
+
+    #[tokio::test]
+    async fn test_classify_errors() {
+        let store = InMemoryInstances::new(vec![create_instance(10)]);
+        let manager = VoteManager::new(&store).retry_delay(Duration::ZERO);
+
+        assert!(matches!(
+            manager.handle(&add(20, 1, 100)).await,
+            Err(VoteError::UnknownMessage)
+        ));
+        assert!(matches!(
+            manager.handle(&add(10, 3, 100)).await,
+            Err(VoteError::UnknownAnswer)
+        ));
+
+        store.fail_saves(MAX_VOTE_ATTEMPTS);
+        assert!(matches!(
+            manager.handle(&add(10, 1, 100)).await,
+            Err(VoteError::Database(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_database_errors() {
+        let store = InMemoryInstances::new(vec![create_instance(10)]);
+
+        store.fail_saves(MAX_VOTE_ATTEMPTS - 1);
+        run(&store, vec![add(10, 1, 100)]).await;
+
+        assert_eq!(
+            vec![PollVote {
+                answer_id: 1,
+                user_id: 100
+            }],
+            store.votes(10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drop_vote_after_attempts() {
+        let store = InMemoryInstances::new(vec![create_instance(10)]);
+
+        // the failing vote is dropped and the next one is still applied
+        store.fail_saves(MAX_VOTE_ATTEMPTS);
+        run(&store, vec![add(10, 1, 100), add(10, 2, 101)]).await;
+
+        assert_eq!(
+            vec![PollVote {
+                answer_id: 2,
+                user_id: 101
+            }],
+            store.votes(10)
+        );
+    }
+}